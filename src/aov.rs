@@ -0,0 +1,254 @@
+use crate::{
+    image::ImageBuffer,
+    integrator::id_color,
+    lpe::Lpe,
+    numbers::Color,
+    ray::{Hit, Lobe, PathEvent, Record},
+    spectrum::Wavelengths,
+};
+
+/// one part of a render kept apart from the rest, for putting the picture
+/// back together in compositing. everything is about the first thing a camera
+/// ray reaches, and scattering in a medium counts as diffuse
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Aov {
+    /// how much light the first surface sends on, averaged over the ways it scatters
+    Albedo,
+    /// the shading normal of the first surface, -1 to 1 along each axis
+    Normal,
+    /// how far away the first surface is, 0 where there's nothing
+    Depth,
+    /// light reaching the first surface straight from a light, the sky or a
+    /// glowing medium and scattered diffusely to the camera
+    DirectDiffuse,
+    /// light the first surface scatters diffusely after it bounced somewhere else
+    IndirectDiffuse,
+    /// all the light the first surface scatters glossily or like a mirror
+    Specular,
+    /// light seen straight from where it's given off, the sky and glowing media
+    /// and surfaces
+    Emission,
+    /// which of the world's objects is seen, counting from 1, or 0 for none
+    ObjectId,
+    /// a made up color for each material seen, the same for materials built alike
+    MaterialId,
+    /// light along paths that match an expression, under a name of its own
+    Custom { name: String, expression: Lpe },
+}
+
+impl Aov {
+    pub const ALL: [Aov; 9] = [
+        Aov::Albedo,
+        Aov::Normal,
+        Aov::Depth,
+        Aov::DirectDiffuse,
+        Aov::IndirectDiffuse,
+        Aov::Specular,
+        Aov::Emission,
+        Aov::ObjectId,
+        Aov::MaterialId,
+    ];
+    /// light along paths matching `expression`, see [`Lpe`]. the name is saved
+    /// under as it is, so it can't be empty, reach into other directories or be
+    /// taken by one of the other passes
+    pub fn custom(name: &str, expression: &str) -> Result<Aov, String> {
+        if name.is_empty() || name.contains(['/', '\\']) || Aov::from_name(name).is_some() {
+            return Err(format!("can't name a pass {:?}", name));
+        }
+        Ok(Aov::Custom { name: name.to_string(), expression: Lpe::new(expression)? })
+    }
+    /// a name to ask for it by and save it under
+    pub fn name(&self) -> &str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::DirectDiffuse => "direct-diffuse",
+            Aov::IndirectDiffuse => "indirect-diffuse",
+            Aov::Specular => "specular",
+            Aov::Emission => "emission",
+            Aov::ObjectId => "object-id",
+            Aov::MaterialId => "material-id",
+            Aov::Custom { name, .. } => name,
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Aov> {
+        Aov::ALL.into_iter().find(|aov| aov.name() == name)
+    }
+    pub fn channels(&self) -> usize {
+        match self {
+            Aov::Depth | Aov::ObjectId => 1,
+            _ => 3,
+        }
+    }
+    /// ids are taken from one sample, since an average of two ids means nothing
+    fn is_id(&self) -> bool {
+        matches!(self, Aov::ObjectId | Aov::MaterialId)
+    }
+    /// which of the passes of light, if any, light along these events goes in
+    fn of_light(events: &[PathEvent]) -> Option<Aov> {
+        use PathEvent::*;
+        let ends = |event: &PathEvent| matches!(event, Light | Background | Emission);
+        match events {
+            [Camera, Background | Emission | Light] => Some(Aov::Emission),
+            [Camera, Surface { lobe: Lobe::Diffuse, .. } | Volume, end] if ends(end) => Some(Aov::DirectDiffuse),
+            [Camera, Surface { lobe: Lobe::Diffuse, .. } | Volume, ..] => Some(Aov::IndirectDiffuse),
+            [Camera, Surface { .. }, ..] => Some(Aov::Specular),
+            _ => None,
+        }
+    }
+}
+
+/// the passes of a single camera sample, filled in as its path is traced
+pub struct AovSample<'a> {
+    aovs: &'a [Aov],
+    wavelengths: Wavelengths,
+    /// all the light, for the picture itself
+    pub beauty: Color,
+    /// the values of each pass, one after another
+    pub values: Vec<f32>,
+}
+
+impl AovSample<'_> {
+    pub fn new(aovs: &[Aov], wavelengths: Wavelengths) -> AovSample<'_> {
+        let size = aovs.iter().map(|aov| aov.channels()).sum();
+        AovSample { aovs, wavelengths, beauty: Color::BLACK, values: vec![0.0; size] }
+    }
+    /// adds to each pass asked for that `fits`
+    fn add_where(&mut self, fits: impl Fn(&Aov) -> bool, value: impl Fn(usize) -> f32) {
+        let mut at = 0;
+        for wanted in self.aovs {
+            if fits(wanted) {
+                for c in 0..wanted.channels() {
+                    self.values[at + c] += value(c);
+                }
+            }
+            at += wanted.channels();
+        }
+    }
+    fn add(&mut self, aov: Aov, value: impl Fn(usize) -> f32) {
+        self.add_where(|wanted| *wanted == aov, value);
+    }
+    fn add_color(&mut self, aov: Aov, color: &Color) {
+        self.add(aov, |c| color.channel(c));
+    }
+}
+
+impl Record for AovSample<'_> {
+    fn light(&mut self, events: &[PathEvent], light: Color) {
+        let light = self.wavelengths.to_rgb(&light);
+        if let Some(aov) = Aov::of_light(events) {
+            self.add_color(aov, &light);
+        }
+        let matches = |aov: &Aov| matches!(aov, Aov::Custom { expression, .. } if expression.matches(events));
+        self.add_where(matches, |c| light.channel(c));
+        self.beauty = self.beauty.clone() + light;
+    }
+    fn hit(&mut self, events: &[PathEvent], hit: &Hit) {
+        if events.len() > 1 {
+            return;
+        }
+        let normal = &hit.normal;
+        self.add(Aov::Normal, |c| [normal.x, normal.y, normal.z][c]);
+        let depth = hit.length * hit.by.direction.length();
+        self.add(Aov::Depth, |_| depth);
+        self.add(Aov::ObjectId, |_| (hit.object + 1) as f32);
+        if self.aovs.contains(&Aov::MaterialId) {
+            self.add_color(Aov::MaterialId, &id_color(hit.material.id()));
+        }
+    }
+    fn scatter(&mut self, events: &[PathEvent], attenuation: &Color) {
+        if events.len() > 1 {
+            return;
+        }
+        // what white light would become, back in rgb
+        let white = self.wavelengths.illuminant(&Color::WHITE);
+        let albedo = self.wavelengths.to_rgb(&(white * attenuation.clone()));
+        self.add_color(Aov::Albedo, &albedo);
+    }
+}
+
+/// sums of the passes of every pixel, built up one pass of samples at a time
+/// alongside the film of the picture itself
+pub struct AovFilm {
+    width: usize,
+    height: usize,
+    aovs: Vec<Aov>,
+    pixels: Vec<AovPixel>,
+}
+
+/// the sums of one pixel's passes and how many samples went into them
+#[derive(Clone, Default)]
+pub struct AovPixel {
+    sums: Vec<f32>,
+    count: u32,
+}
+
+impl AovFilm {
+    pub fn new(width: usize, height: usize, aovs: &[Aov]) -> AovFilm {
+        let size = aovs.iter().map(|aov| aov.channels()).sum();
+        let pixel = AovPixel { sums: vec![0.0; size], count: 0 };
+        AovFilm { width, height, aovs: aovs.to_vec(), pixels: vec![pixel; width * height] }
+    }
+    /// the width and height
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+    pub fn aovs(&self) -> &[Aov] {
+        &self.aovs
+    }
+    pub fn pixels_mut(&mut self) -> &mut [AovPixel] {
+        &mut self.pixels
+    }
+    /// averages each pass into an image of its own
+    pub fn to_images(&self) -> Vec<(Aov, ImageBuffer)> {
+        let mut at = 0;
+        let mut images = Vec::new();
+        for aov in &self.aovs {
+            let channels = aov.channels();
+            let values = self
+                .pixels
+                .iter()
+                .flat_map(|pixel| {
+                    let scale = if aov.is_id() || pixel.count == 0 { 1.0 } else { 1.0 / pixel.count as f32 };
+                    pixel.sums[at..at + channels].iter().map(move |v| v * scale)
+                })
+                .collect();
+            images.push((aov.clone(), ImageBuffer::from_channels(self.width, self.height, channels, values).unwrap()));
+            at += channels;
+        }
+        images
+    }
+}
+
+impl AovPixel {
+    /// adds one sample's passes, keeping the ids of the first
+    pub fn add(&mut self, aovs: &[Aov], sample: &AovSample) {
+        let mut at = 0;
+        for aov in aovs {
+            for c in at..at + aov.channels() {
+                if !aov.is_id() {
+                    self.sums[c] += sample.values[c];
+                } else if self.count == 0 {
+                    self.sums[c] = sample.values[c];
+                }
+            }
+            at += aov.channels();
+        }
+        self.count += 1;
+    }
+}
+
+#[test]
+fn test_light_goes_in_the_right_pass() {
+    use PathEvent::*;
+    let diffuse = Surface { lobe: Lobe::Diffuse, through: false };
+    let glossy = Surface { lobe: Lobe::Glossy, through: true };
+    assert_eq!(Aov::of_light(&[Camera, Background]), Some(Aov::Emission));
+    assert_eq!(Aov::of_light(&[Camera, diffuse, Light]), Some(Aov::DirectDiffuse));
+    assert_eq!(Aov::of_light(&[Camera, diffuse, glossy, Light]), Some(Aov::IndirectDiffuse));
+    assert_eq!(Aov::of_light(&[Camera, glossy, diffuse, Background]), Some(Aov::Specular));
+    assert!(Aov::ALL.iter().all(|aov| Aov::from_name(aov.name()).as_ref() == Some(aov)));
+    assert!(Aov::custom("caustics", "CD S+ L").is_ok());
+    assert!(["", "albedo", "../caustics"].iter().all(|name| Aov::custom(name, "CDL").is_err()));
+}
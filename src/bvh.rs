@@ -0,0 +1,101 @@
+use crate::{
+    numbers::Aabb,
+    ray::{Hit, Ray},
+};
+
+/// most items kept together in one leaf
+const LEAF_SIZE: usize = 4;
+
+enum Node {
+    Leaf { bounds: Aabb, start: usize, count: usize },
+    Branch { bounds: Aabb, left: usize, right: usize },
+}
+
+impl Node {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Branch { bounds, .. } => bounds,
+        }
+    }
+}
+
+/// a bounding volume hierarchy over a list of items, stored only as indices,
+/// so it can sit alongside whatever owns the items
+pub struct Bvh {
+    nodes: Vec<Node>,
+    /// item indices, grouped so each leaf covers a contiguous run
+    items: Vec<usize>,
+}
+
+impl Bvh {
+    /// builds a tree over items with the given bounds, splitting at the median
+    /// of the longest axis
+    pub fn new(bounds: &[Aabb]) -> Bvh {
+        let mut bvh = Bvh {
+            nodes: Vec::new(),
+            items: (0..bounds.len()).collect(),
+        };
+        if !bounds.is_empty() {
+            bvh.build(bounds, 0, bounds.len());
+        }
+        bvh
+    }
+    fn build(&mut self, bounds: &[Aabb], start: usize, end: usize) -> usize {
+        let items = &mut self.items[start..end];
+        let total = items.iter().fold(Aabb::EMPTY, |b, &i| b.union(&bounds[i]));
+        let index = self.nodes.len();
+        if items.len() <= LEAF_SIZE {
+            self.nodes.push(Node::Leaf { bounds: total, start, count: items.len() });
+            return index;
+        }
+        let centers = items.iter().fold(Aabb::EMPTY, |b, &i| b.including(&bounds[i].center()));
+        let axis = centers.longest_axis();
+        let middle = items.len() / 2;
+        items.select_nth_unstable_by(middle, |&a, &b| {
+            let a = bounds[a].center().axis(axis);
+            let b = bounds[b].center().axis(axis);
+            a.total_cmp(&b)
+        });
+        // placeholder until both children exist
+        self.nodes.push(Node::Leaf { bounds: total, start, count: 0 });
+        let left = self.build(bounds, start, start + middle);
+        let right = self.build(bounds, start + middle, end);
+        self.nodes[index] = Node::Branch { bounds: total, left, right };
+        index
+    }
+    /// finds the nearest hit, asking `hit_item` about each item whose box the ray
+    /// passes through, along with the farthest length still worth reporting
+    pub fn hit(&self, ray: &Ray, near: f32, far: f32, hit_item: impl Fn(usize, f32) -> Option<Hit>) -> Option<Hit> {
+        let mut nearest: Option<Hit> = None;
+        let mut far = far;
+        let mut stack = Vec::with_capacity(32);
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.bounds().clip(&ray.origin, &ray.direction, near, far).is_none() {
+                continue;
+            }
+            match node {
+                Node::Leaf { start, count, .. } => {
+                    for &item in &self.items[*start..start + count] {
+                        if let Some(hit) = hit_item(item, far) {
+                            far = hit.length;
+                            nearest = Some(hit);
+                        }
+                    }
+                }
+                Node::Branch { left, right, .. } => {
+                    stack.push(*right);
+                    stack.push(*left);
+                }
+            }
+        }
+        nearest
+    }
+    /// the box around everything in the tree
+    pub fn bounds(&self) -> Aabb {
+        self.nodes.first().map(|n| *n.bounds()).unwrap_or(Aabb::EMPTY)
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, ErrorKind, Read, Write},
+    path::Path,
+};
+
+use crate::{image::Film, numbers::Samples};
+
+/// identifies a checkpoint file
+const MAGIC: &[u8; 4] = b"WRCP";
+/// bumped whenever the layout below changes
+const VERSION: u32 = 2;
+/// the bytes before the pixels, and the bytes of each pixel after them
+const HEADER_BYTES: u64 = 36;
+const PIXEL_BYTES: u64 = 16;
+
+/// the state of a render after some number of whole passes,
+/// enough to continue it as if it had never stopped
+///
+/// file layout, all values little-endian:
+/// magic, version, width, height, seed (u64), samples per pass, passes done,
+/// spectral (1 or 0), then r, g, b sums (f32) and sample count (u32) for every pixel
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    /// base seed the per-pixel generators are derived from
+    pub seed: u64,
+    /// samples traced for every pixel in each pass
+    pub samples_per_pass: u32,
+    /// number of passes already accumulated into the film
+    pub passes: u32,
+    /// whether the samples traced wavelengths rather than rgb
+    pub spectral: bool,
+    pub film: Film,
+}
+
+impl Checkpoint {
+    /// writes the checkpoint next to `path` and then moves it into place,
+    /// so an interrupted save never clobbers the previous checkpoint
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let partial = path.with_extension("partial");
+        {
+            let mut out = BufWriter::new(File::create(&partial)?);
+            out.write_all(MAGIC)?;
+            out.write_all(&VERSION.to_le_bytes())?;
+            out.write_all(&(self.film.width() as u32).to_le_bytes())?;
+            out.write_all(&(self.film.height() as u32).to_le_bytes())?;
+            out.write_all(&self.seed.to_le_bytes())?;
+            out.write_all(&self.samples_per_pass.to_le_bytes())?;
+            out.write_all(&self.passes.to_le_bytes())?;
+            out.write_all(&u32::from(self.spectral).to_le_bytes())?;
+            for samples in self.film.samples() {
+                let (r, g, b, count) = samples.sums();
+                out.write_all(&r.to_le_bytes())?;
+                out.write_all(&g.to_le_bytes())?;
+                out.write_all(&b.to_le_bytes())?;
+                out.write_all(&count.to_le_bytes())?;
+            }
+            out.flush()?;
+        }
+        fs::rename(&partial, path)
+    }
+    pub fn load(path: &Path) -> io::Result<Checkpoint> {
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+        let mut input = BufReader::new(file);
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(ErrorKind::InvalidData, "not a checkpoint file"));
+        }
+        let version = read_u32(&mut input)?;
+        if version != VERSION {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("unsupported checkpoint version {}", version),
+            ));
+        }
+        let width = read_u32(&mut input)? as usize;
+        let height = read_u32(&mut input)? as usize;
+        let mut seed = [0; 8];
+        input.read_exact(&mut seed)?;
+        let seed = u64::from_le_bytes(seed);
+        let samples_per_pass = read_u32(&mut input)?;
+        let passes = read_u32(&mut input)?;
+        let spectral = match read_u32(&mut input)? {
+            0 => false,
+            1 => true,
+            _ => return Err(io::Error::new(ErrorKind::InvalidData, "not a checkpoint file")),
+        };
+        // check the header against the file before trusting it with an allocation
+        let count = width
+            .checked_mul(height)
+            .filter(|&count| count > 0 && (count as u64).saturating_mul(PIXEL_BYTES) <= length.saturating_sub(HEADER_BYTES))
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "truncated checkpoint"))?;
+        let mut pixels = Vec::with_capacity(count);
+        for _ in 0..count {
+            let r = f32::from_bits(read_u32(&mut input)?);
+            let g = f32::from_bits(read_u32(&mut input)?);
+            let b = f32::from_bits(read_u32(&mut input)?);
+            let count = read_u32(&mut input)?;
+            pixels.push(Samples::from_sums(r, g, b, count));
+        }
+        let film = Film::from_samples(width, height, pixels)
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "truncated checkpoint"))?;
+        Ok(Checkpoint {
+            seed,
+            samples_per_pass,
+            passes,
+            spectral,
+            film,
+        })
+    }
+}
+
+fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+#[test]
+fn test_checkpoint_round_trip() {
+    let mut film = Film::new(3, 2);
+    film.samples_mut()[4] = Samples::from_sums(0.5, 1.25, 3.0, 7);
+    let checkpoint = Checkpoint {
+        seed: 99,
+        samples_per_pass: 4,
+        passes: 2,
+        spectral: true,
+        film,
+    };
+    let path = std::env::temp_dir().join(format!("checkpoint-test-{}.bin", std::process::id()));
+    checkpoint.save(&path).unwrap();
+    let loaded = Checkpoint::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(loaded == checkpoint);
+}
+
+#[test]
+fn test_checkpoint_rejects_bad_sizes() {
+    let path = std::env::temp_dir().join(format!("checkpoint-sizes-{}.bin", std::process::id()));
+    for (width, height) in [(0u32, 4u32), (u32::MAX, u32::MAX), (1000, 1000)] {
+        let mut bytes = MAGIC.to_vec();
+        for value in [VERSION, width, height, 0, 0, 1, 1, 0] {
+            bytes.extend(value.to_le_bytes());
+        }
+        fs::write(&path, bytes).unwrap();
+        assert_eq!(Checkpoint::load(&path).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+    fs::remove_file(&path).unwrap();
+}
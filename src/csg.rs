@@ -0,0 +1,142 @@
+use std::sync::Arc;
+
+use crate::{
+    numbers::Aabb,
+    ray::{Hit, Ray},
+    world::{Hittable, Solid, Span},
+};
+
+/// how two solids are combined
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operation {
+    /// inside either
+    Union,
+    /// inside both
+    Intersection,
+    /// inside the first but not the second
+    Difference,
+}
+
+impl Operation {
+    fn inside(&self, a: bool, b: bool) -> bool {
+        match self {
+            Operation::Union => a || b,
+            Operation::Intersection => a && b,
+            Operation::Difference => a && !b,
+        }
+    }
+}
+
+/// a solid built from two others by constructive solid geometry.
+/// surfaces keep the material of the solid they came from, so the walls
+/// cut by a difference are drawn with the material of the cutter
+pub struct Csg {
+    operation: Operation,
+    a: Arc<dyn Solid>,
+    b: Arc<dyn Solid>,
+}
+
+impl Csg {
+    pub fn new(operation: Operation, a: Arc<dyn Solid>, b: Arc<dyn Solid>) -> Csg {
+        Csg { operation, a, b }
+    }
+    pub fn union(a: impl Solid + 'static, b: impl Solid + 'static) -> Csg {
+        Csg::new(Operation::Union, Arc::new(a), Arc::new(b))
+    }
+    pub fn intersection(a: impl Solid + 'static, b: impl Solid + 'static) -> Csg {
+        Csg::new(Operation::Intersection, Arc::new(a), Arc::new(b))
+    }
+    /// `a` with `b` carved out of it
+    pub fn difference(a: impl Solid + 'static, b: impl Solid + 'static) -> Csg {
+        Csg::new(Operation::Difference, Arc::new(a), Arc::new(b))
+    }
+}
+
+/// marks a boundary hit as going into or out of the combined solid.
+/// normals always face back along the ray, so only the side changes
+fn boundary(mut hit: Hit, entering: bool) -> Hit {
+    hit.front = entering;
+    hit
+}
+
+impl Solid for Csg {
+    fn spans(&self, ray: &Ray) -> Vec<Span> {
+        // every boundary of either solid, in order along the ray
+        let mut events: Vec<(Hit, bool, bool)> = Vec::new();
+        for (spans, from_a) in [(self.a.spans(ray), true), (self.b.spans(ray), false)] {
+            for Span { enter, exit } in spans {
+                events.push((enter, from_a, true));
+                events.push((exit, from_a, false));
+            }
+        }
+        events.sort_by(|x, y| x.0.length.total_cmp(&y.0.length));
+
+        let (mut in_a, mut in_b, mut inside) = (false, false, false);
+        let mut enter = None;
+        let mut spans = Vec::new();
+        for (hit, from_a, entering) in events {
+            if from_a {
+                in_a = entering;
+            } else {
+                in_b = entering;
+            }
+            let now_inside = self.operation.inside(in_a, in_b);
+            if now_inside && !inside {
+                enter = Some(boundary(hit, true));
+            } else if inside && !now_inside {
+                if let Some(enter) = enter.take() {
+                    spans.push(Span { enter, exit: boundary(hit, false) });
+                }
+            }
+            inside = now_inside;
+        }
+        spans
+    }
+}
+
+impl Hittable for Csg {
+    fn hit(&self, ray: &Ray, near: f32, far: f32) -> Option<Hit> {
+        let bounds = self.bounds()?;
+        bounds.clip(&ray.origin, &ray.direction, near, far)?;
+        self.spans(ray)
+            .into_iter()
+            .flat_map(|Span { enter, exit }| [enter, exit])
+            .find(|hit| near <= hit.length && hit.length <= far)
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        let a = self.a.bounds()?;
+        match self.operation {
+            Operation::Union => Some(a.union(&self.b.bounds()?)),
+            Operation::Intersection => {
+                // when the boxes don't overlap this is inside out, and no ray gets through it
+                let b = self.b.bounds()?;
+                Some(Aabb { min: a.min.max(&b.min), max: a.max.min(&b.max) })
+            }
+            Operation::Difference => Some(a),
+        }
+    }
+}
+
+#[test]
+fn test_csg_spans() {
+    use crate::{numbers::Vector, world::Sphere};
+    let ray = Ray::new(Vector::new(-5.0, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0));
+    let left = || Sphere::new(-0.5, 0.0, 0.0, 1.0);
+    let right = || Sphere::new(0.5, 0.0, 0.0, 1.0);
+    let lengths = |csg: Csg| -> Vec<(f32, f32)> {
+        csg.spans(&ray).iter().map(|s| (s.enter.length, s.exit.length)).collect()
+    };
+    let close = |a: Vec<(f32, f32)>, b: &[(f32, f32)]| {
+        a.len() == b.len() && a.iter().zip(b).all(|(x, y)| (x.0 - y.0).abs() < 1e-4 && (x.1 - y.1).abs() < 1e-4)
+    };
+    assert!(close(lengths(Csg::union(left(), right())), &[(3.5, 6.5)]));
+    assert!(close(lengths(Csg::intersection(left(), right())), &[(4.5, 5.5)]));
+    assert!(close(lengths(Csg::difference(left(), right())), &[(3.5, 4.5)]));
+
+    // a hollow shell has two walls, and hitting from inside the hole finds the inner wall
+    let shell = Csg::difference(Sphere::new(0.0, 0.0, 0.0, 1.0), Sphere::new(0.0, 0.0, 0.0, 0.5));
+    assert!(close(shell.spans(&ray).iter().map(|s| (s.enter.length, s.exit.length)).collect(), &[(4.0, 4.5), (5.5, 6.0)]));
+    let from_hole = shell.hit(&Ray::new(Vector::ORIGIN, Vector::new(1.0, 0.0, 0.0)), 0.0, f32::INFINITY).unwrap();
+    assert!((from_hole.length - 0.5).abs() < 1e-4 && from_hole.front);
+    assert!((from_hole.normal - Vector::new(-1.0, 0.0, 0.0)).length() < 1e-4);
+}
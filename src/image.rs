@@ -1,8 +1,11 @@
-use std::ops::{Add, Mul};
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    ops::Add,
+    path::Path,
+};
 
-use indicatif::ProgressBar;
-
-use crate::numbers::Color;
+use crate::numbers::{Color, Samples};
 
 /// RGB format with channel values from 0-255
 /// expected to be gamma-corrected
@@ -30,14 +33,18 @@ impl Pixel {
     }
 }
 
-/// simple image buffer with width and height
+/// simple image buffer with width and height, holding linear color or a
+/// single channel of values, kept as gray
 pub struct ImageBuffer {
     width: usize,
     height: usize,
-    buffer: Vec<Pixel>,
+    /// 1 or 3
+    channels: usize,
+    buffer: Vec<Color>,
 }
 
 impl ImageBuffer {
+    /// an image filled with a test gradient
     pub fn new(width: usize, height: usize) -> ImageBuffer {
         let mut buffer = Vec::with_capacity(width * height);
         let max_width = width as f32 - 1.0;
@@ -49,36 +56,218 @@ impl ImageBuffer {
                 let g = (y as f32) / max_height;
                 let b = 0.25;
                 let c = Color::new(r,g,b);
-                buffer.push(c.to_pixel());
+                buffer.push(c);
             }
         }
         ImageBuffer {
             width,
             height,
+            channels: 3,
             buffer,
         }
     }
-    pub fn swap_pixels(&mut self, mut other: Vec<Pixel>) {
+    /// wraps existing colors, which must hold exactly width * height entries in row order
+    pub fn from_pixels(width: usize, height: usize, buffer: Vec<Color>) -> Option<ImageBuffer> {
+        if buffer.len() == width * height {
+            Some(ImageBuffer { width, height, channels: 3, buffer })
+        } else {
+            None
+        }
+    }
+    /// wraps existing values, which must hold exactly `channels`, 1 or 3, for
+    /// each of width * height pixels in row order
+    pub fn from_channels(width: usize, height: usize, channels: usize, values: Vec<f32>) -> Option<ImageBuffer> {
+        if !matches!(channels, 1 | 3) || values.len() != width * height * channels {
+            return None;
+        }
+        let buffer = match channels {
+            1 => values.into_iter().map(Color::gray).collect(),
+            _ => values.chunks(3).map(|c| Color::new(c[0], c[1], c[2])).collect(),
+        };
+        Some(ImageBuffer { width, height, channels, buffer })
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    /// the number of values for each pixel
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+    /// one channel at column x of row y, counting rows from the top
+    pub fn value(&self, x: usize, y: usize, channel: usize) -> f32 {
+        self.pixel(x, y).channel(channel)
+    }
+    /// the color at column x of row y, counting rows from the top. a single
+    /// channel is gray
+    pub fn pixel(&self, x: usize, y: usize) -> &Color {
+        &self.buffer[y * self.width + x]
+    }
+    /// every color in row order, starting at the top left
+    pub fn pixels(&self) -> &[Color] {
+        &self.buffer
+    }
+    /// replaces the contents with the given colors if there are the right number of them
+    pub fn swap_pixels(&mut self, mut other: Vec<Color>) {
         if other.len() == self.buffer.len() {
             self.buffer.swap_with_slice(&mut other)
         }
     }
 
+    /// gamma-corrected plain-text ppm
     pub fn serialize_ppm(&self) -> String {
-        let bar = ProgressBar::new((self.width * self.height + 1) as u64);
-
         let pixels: String = self
             .buffer
             .iter()
-            .map(|p| {
-                bar.inc(1);
-                p.serialize_ppm()
-            })
+            .map(|c| c.to_pixel().serialize_ppm())
             .collect();
 
-        let o = format!("P3\n{} {}\n{}\n{}", self.width, self.height, 255, pixels);
-        bar.inc(1);
-        bar.finish();
-        o
+        format!("P3\n{} {}\n{}\n{}", self.width, self.height, 255, pixels)
+    }
+    /// reads a plain-text (p3) or binary (p6) ppm, with channels scaled to 0-1
+    /// but otherwise as stored, which is what data like normal maps needs
+    pub fn load_ppm(path: &Path) -> io::Result<ImageBuffer> {
+        let bytes = fs::read(path)?;
+        ImageBuffer::parse_ppm(&bytes).ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "not a readable ppm"))
+    }
+    pub fn parse_ppm(bytes: &[u8]) -> Option<ImageBuffer> {
+        // the header is whitespace separated, with comments from # to the end of a line
+        let mut at = 0;
+        let mut token = || -> Option<&[u8]> {
+            loop {
+                while bytes.get(at)?.is_ascii_whitespace() {
+                    at += 1;
+                }
+                if bytes[at] != b'#' {
+                    break;
+                }
+                while *bytes.get(at)? != b'\n' {
+                    at += 1;
+                }
+            }
+            let start = at;
+            while at < bytes.len() && !bytes[at].is_ascii_whitespace() {
+                at += 1;
+            }
+            Some(&bytes[start..at])
+        };
+        let number = |token: &[u8]| std::str::from_utf8(token).ok()?.parse::<usize>().ok();
+        let magic = token()?;
+        let width = number(token()?)?;
+        let height = number(token()?)?;
+        let max = number(token()?)?.max(1);
+        let count = width.checked_mul(height)?.checked_mul(3)?;
+        let values: Vec<usize> = match magic {
+            b"P3" => (0..count).map(|_| number(token()?)).collect::<Option<_>>()?,
+            b"P6" => {
+                // a single whitespace byte separates the header from the data
+                let data = bytes.get(at + 1..)?;
+                if max < 256 {
+                    data.get(..count)?.iter().map(|&b| b as usize).collect()
+                } else {
+                    data.get(..count.checked_mul(2)?)?.chunks(2).map(|c| (c[0] as usize) << 8 | c[1] as usize).collect()
+                }
+            }
+            _ => return None,
+        };
+        let scale = 1.0 / max as f32;
+        let buffer = values
+            .chunks(3)
+            .map(|c| Color::new(c[0] as f32 * scale, c[1] as f32 * scale, c[2] as f32 * scale))
+            .collect();
+        ImageBuffer::from_pixels(width, height, buffer)
+    }
+    /// undoes the gamma `serialize_ppm` applies, giving linear color for
+    /// pictures read back in to use as textures
+    pub fn decode_gamma(&self) -> ImageBuffer {
+        let buffer = self.buffer.iter().map(|c| c.clone() * c.clone()).collect();
+        ImageBuffer { buffer, ..*self }
+    }
+    /// little-endian pfm, which keeps values as they are: gray for one channel
+    /// and color for three
+    pub fn serialize_pfm(&self) -> Vec<u8> {
+        let gray = self.channels == 1;
+        let mut out = format!("{}\n{} {}\n-1.0\n", if gray { "Pf" } else { "PF" }, self.width, self.height).into_bytes();
+        // rows go from the bottom up
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let color = self.pixel(x, y);
+                let values = if gray { vec![color.r()] } else { vec![color.r(), color.g(), color.b()] };
+                for value in values {
+                    out.extend(value.to_le_bytes());
+                }
+            }
+        }
+        out
+    }
+    pub fn save_pfm(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.serialize_pfm())
+    }
+}
+
+/// per-pixel sums of every sample traced so far, built up one pass at a time
+#[derive(Clone, Debug, PartialEq)]
+pub struct Film {
+    width: usize,
+    height: usize,
+    pixels: Vec<Samples>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Film {
+        Film {
+            width,
+            height,
+            pixels: vec![Samples::NONE; width * height],
+        }
     }
+    /// wraps existing sums, which must hold exactly width * height entries
+    pub fn from_samples(width: usize, height: usize, pixels: Vec<Samples>) -> Option<Film> {
+        if pixels.len() == width * height {
+            Some(Film { width, height, pixels })
+        } else {
+            None
+        }
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    pub fn samples(&self) -> &[Samples] {
+        &self.pixels
+    }
+    pub fn samples_mut(&mut self) -> &mut [Samples] {
+        &mut self.pixels
+    }
+    /// averages the samples of each pixel into a linear color image
+    pub fn to_image(&self) -> ImageBuffer {
+        let mut image = ImageBuffer::new(self.width, self.height);
+        image.swap_pixels(self.pixels.iter().map(|s| s.to_color()).collect());
+        image
+    }
+}
+
+#[test]
+fn test_parse_ppm() {
+    let plain = ImageBuffer::parse_ppm(b"P3\n# a comment\n2 1\n255\n255 0 0  0 0 51\n").unwrap();
+    assert!(plain.width() == 2 && plain.pixel(0, 0).r() == 1.0 && (plain.pixel(1, 0).b() - 0.2).abs() < 1e-6);
+    let mut binary = b"P6 1 1 255\n".to_vec();
+    binary.extend([0, 128, 255]);
+    let binary = ImageBuffer::parse_ppm(&binary).unwrap();
+    assert!(binary.pixel(0, 0).b() == 1.0 && binary.pixel(0, 0).r() == 0.0);
+    assert!(ImageBuffer::parse_ppm(b"P3 2 2 255 0 0 0").is_none());
+    assert!(ImageBuffer::parse_ppm(b"P6 18446744073709551615 2 255\n\0\0\0").is_none());
+}
+
+#[test]
+fn test_pfm_rows_run_bottom_up() {
+    let depth = ImageBuffer::from_channels(1, 2, 1, vec![1.0, 2.0]).unwrap();
+    let pfm = depth.serialize_pfm();
+    assert!(pfm.starts_with(b"Pf\n1 2\n-1.0\n"));
+    assert_eq!(pfm[pfm.len() - 8..], [2.0f32.to_le_bytes(), 1.0f32.to_le_bytes()].concat());
+    assert!(ImageBuffer::from_channels(2, 2, 4, vec![0.0; 15]).is_none());
 }
@@ -0,0 +1,78 @@
+use std::sync::Arc;
+
+use crate::{
+    material::Material,
+    numbers::{Aabb, Transform},
+    ray::{Hit, Ray},
+    world::Hittable,
+};
+
+/// a shared object placed in the world with its own transform and,
+/// optionally, its own material. the object itself is never copied,
+/// so a heavy mesh can be placed many times for the cost of a matrix each
+pub struct Instance {
+    object: Arc<dyn Hittable>,
+    transform: Transform,
+    material: Option<Arc<Material>>,
+}
+
+impl Instance {
+    /// places the object, which is described in its own space, by the transform
+    pub fn new(object: Arc<dyn Hittable>, transform: Transform) -> Instance {
+        Instance {
+            object,
+            transform,
+            material: None,
+        }
+    }
+    /// draws the object with this material instead of its own
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = Some(Arc::new(material));
+        self
+    }
+    /// draws the object with a material that is shared with other objects
+    pub fn with_shared_material(mut self, material: Arc<Material>) -> Self {
+        self.material = Some(material);
+        self
+    }
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray: &Ray, near: f32, far: f32) -> Option<Hit> {
+        // the direction is not renormalized, so lengths along the ray
+        // mean the same thing in both spaces
+        let inverse = self.transform.inverse();
+        let local = Ray::new(inverse.point(&ray.origin), inverse.vector(&ray.direction));
+        let mut hit = self.object.hit(&local, near, far)?;
+        hit.by = *ray;
+        hit.pos = self.transform.point(&hit.pos);
+        hit.normal = self.transform.normal(&hit.normal).unit();
+        hit.geometric = self.transform.normal(&hit.geometric).unit();
+        hit.tangent = self.transform.vector(&hit.tangent).unit();
+        if let Some(material) = &self.material {
+            hit.material = material.clone();
+        }
+        Some(hit)
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        self.object.bounds().map(|b| self.transform.bounds(&b))
+    }
+}
+
+#[test]
+fn test_instance_matches_moved_sphere() {
+    use crate::{numbers::Vector, world::Sphere};
+    let unit = Arc::new(Sphere::new(0.0, 0.0, 0.0, 1.0));
+    let moved = Instance::new(unit, Transform::scale(0.5, 0.5, 0.5).then(&Transform::translate(0.0, 1.0, -3.0)));
+    let direct = Sphere::new(0.0, 1.0, -3.0, 0.5);
+    let ray = Ray::new(Vector::new(0.1, 0.2, 0.0), Vector::new(0.0, 0.3, -1.0));
+    let a = moved.hit(&ray, 0.0, f32::INFINITY).unwrap();
+    let b = direct.hit(&ray, 0.0, f32::INFINITY).unwrap();
+    assert!((a.length - b.length).abs() < 1e-4);
+    assert!((a.pos - b.pos).length() < 1e-4);
+    assert!((a.normal - b.normal).length() < 1e-4);
+    assert!(a.front == b.front);
+}
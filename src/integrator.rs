@@ -0,0 +1,121 @@
+use crate::{
+    material::Shader,
+    numbers::{mix_seed, Color, Frame, Vector},
+    ray::Ray,
+    world::World,
+};
+
+/// how a camera ray is turned into a color. everything but `Path` is for
+/// looking at a scene's shapes and surfaces rather than its lighting
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Integrator {
+    /// light carried along whole paths, the real picture
+    #[default]
+    Path,
+    /// white where a surface is open to the sky, darker the more of it is
+    /// blocked by anything within `radius`
+    AmbientOcclusion { radius: f32 },
+    /// the shading normal, from black at -1 to full at 1 along each axis
+    Normals,
+    /// the distance to the first surface, white up close fading to black at `far`
+    Depth { far: f32 },
+    /// texture coordinates as red and green, repeating past 1
+    Uv,
+    /// a color for every material, the same for materials built alike
+    MaterialId,
+    /// how much each corner of a triangle counts at the hit, as red, green and
+    /// blue. black on anything but triangles
+    Barycentrics,
+    /// how many times a path bounces before it gets away or is absorbed, from
+    /// blue for none through green to red at `most`
+    Bounces { most: usize },
+}
+
+impl Integrator {
+    /// the color seen along a camera ray, following paths at most `max_bounces` long
+    pub fn cast(&self, ray: &Ray, world: &World, max_bounces: usize) -> Color {
+        match self {
+            Integrator::Path => ray.cast(world, max_bounces),
+            Integrator::AmbientOcclusion { radius } => occlusion(ray, world, *radius),
+            Integrator::Bounces { most } => heat(bounces(ray, world, *most) as f32 / (*most).max(1) as f32),
+            _ => {
+                let Some(hit) = world.hit(ray) else {
+                    return Color::BLACK;
+                };
+                match self {
+                    Integrator::Normals => hit.normal.as_color(),
+                    Integrator::Depth { far } => {
+                        let distance = hit.length * ray.direction.length();
+                        Color::gray((1.0 - distance / far).clamp(0.0, 1.0))
+                    }
+                    Integrator::Uv => Color::new(hit.uv.u.rem_euclid(1.0), hit.uv.v.rem_euclid(1.0), 0.0),
+                    Integrator::MaterialId => id_color(hit.material.id()),
+                    Integrator::Barycentrics => match hit.barycentric {
+                        Some((u, v)) => Color::new(1.0 - u - v, u, v),
+                        None => Color::BLACK,
+                    },
+                    _ => unreachable!(),
+                }
+            }
+        }
+    }
+}
+
+/// one look out from the first surface in a direction picked by the cosine,
+/// white if nothing is found within `radius`
+fn occlusion(ray: &Ray, world: &World, radius: f32) -> Color {
+    let Some(hit) = world.hit(ray) else {
+        return Color::WHITE;
+    };
+    let direction = Frame::from_z(hit.normal).to_world(&Vector::random_cosine());
+    let blocked = world.hit(&Ray::new(hit.pos, direction)).is_some_and(|blocker| blocker.length < radius);
+    if blocked { Color::BLACK } else { Color::WHITE }
+}
+
+/// the number of times a path scatters off surfaces, up to `most`
+fn bounces(ray: &Ray, world: &World, most: usize) -> usize {
+    let mut ray = *ray;
+    let mut count = 0;
+    while count < most {
+        let Some(hit) = world.hit(&ray) else { break };
+        let material = hit.material.clone();
+        let Some(bounce) = material.scatter(hit) else { break };
+        ray = bounce.ray;
+        count += 1;
+    }
+    count
+}
+
+/// blue at 0, green halfway and red at 1
+fn heat(t: f32) -> Color {
+    let t = t.clamp(0.0, 1.0);
+    if t < 0.5 {
+        Color::blend(Color::new(0.0, 0.0, 1.0), Color::new(0.0, 1.0, 0.0), t * 2.0)
+    } else {
+        Color::blend(Color::new(0.0, 1.0, 0.0), Color::new(1.0, 0.0, 0.0), t * 2.0 - 1.0)
+    }
+}
+
+/// a bright, made up color for a number, far from the colors of numbers near it
+pub fn id_color(id: u64) -> Color {
+    let hash = mix_seed(id, 0, 0);
+    let channel = |shift: u32| 0.2 + 0.8 * ((hash >> shift) & 0xff) as f32 / 255.0;
+    Color::new(channel(0), channel(8), channel(16))
+}
+
+#[test]
+fn test_heat_runs_blue_to_red() {
+    assert_eq!(heat(0.0), Color::new(0.0, 0.0, 1.0));
+    assert_eq!(heat(0.5), Color::new(0.0, 1.0, 0.0));
+    assert_eq!(heat(2.0), Color::new(1.0, 0.0, 0.0));
+    assert_eq!(id_color(7), id_color(7));
+    assert_ne!(id_color(7), id_color(8));
+    // ids come from what a material is, not where it was put in memory
+    use crate::material::Material;
+    assert_eq!(Material::gold(0.2).id(), Material::gold(0.2).id());
+    assert_ne!(Material::gold(0.2).id(), Material::gold(0.3).id());
+    assert_ne!(Material::Metal(0.2, Color::RED).id(), Material::Diffuse(0.2, Color::RED).id());
+    assert_eq!(Material::Diffuse(0.0, Color::RED).id(), Material::Diffuse(-0.0, Color::RED).id());
+    // nor which version of rust worked them out: fnv-1a over the variant, roughness and color
+    assert_eq!(Material::new().id(), 16818069894397888308);
+}
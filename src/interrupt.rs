@@ -0,0 +1,24 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+    // a second ctrl-c falls through to the default handler and kills the process
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+    }
+}
+
+/// catches the first SIGINT so the render can stop at the end of its current pass
+pub fn install() {
+    let handler = on_interrupt as extern "C" fn(libc::c_int);
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+}
+
+/// true once SIGINT has been received
+pub fn requested() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
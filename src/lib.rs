@@ -0,0 +1,74 @@
+//! a small path tracer, following "ray tracing in one weekend"
+//!
+//! build a [`world::World`] out of spheres and [`material::Material`]s,
+//! point a [`camera::Camera`] at it and hand both to a [`render::Renderer`]:
+//!
+//! ```
+//! use weekend_raytrace::{
+//!     camera::Camera,
+//!     material::Material,
+//!     render::{RenderSettings, Renderer},
+//!     world::{Sphere, World},
+//! };
+//!
+//! let world = World::empty()
+//!     .with(Sphere::new(0.0, 0.0, -1.0, 0.5).with_material(Material::TEST_METAL_BLUE))
+//!     .with(Sphere::new(0.0, -100.5, -1.0, 100.0));
+//! let camera = Camera::new(2.0, 2.0);
+//! let settings = RenderSettings { width: 16, height: 16, samples: 4, ..Default::default() };
+//! let image = Renderer::new(settings).render(&world, &camera);
+//! assert_eq!(image.width(), 16);
+//! ```
+
+/// render passes kept apart for compositing
+pub mod aov;
+/// bounding volume hierarchies for quickly finding what a ray hits
+pub mod bvh;
+/// handling for view transforms
+pub mod camera;
+/// solids combined by union, intersection and difference
+pub mod csg;
+/// saving and restoring partially finished renders
+pub mod checkpoint;
+/// image buffer
+pub mod image;
+/// shared objects placed with their own transforms
+pub mod instance;
+/// ways of turning camera rays into colors, besides tracing paths
+pub mod integrator;
+/// point, spot, directional and glowing triangle lights
+pub mod light;
+/// choosing which light to sample, by power or with a tree of lights
+pub mod light_tree;
+/// physical materials for meshes
+pub mod material;
+/// fog, smoke and other participating media
+pub mod medium;
+/// light path expressions, for passes of light that took chosen paths
+pub mod lpe;
+/// triangle meshes
+pub mod mesh;
+/// microfacet distributions shared by rough materials
+pub mod microfacet;
+/// numerical primatives like Vector
+pub mod numbers;
+/// one material with the parameters artists know from other tools
+pub mod principled;
+/// main ray casting functionality
+pub mod ray;
+/// driving passes of samples over a whole image
+pub mod render;
+/// named, nested groups of objects
+pub mod scene;
+/// shapes drawn from signed distance functions
+pub mod sdf;
+/// analytic primitives besides the sphere
+pub mod shapes;
+/// wavelengths of light, for effects that split white light apart
+pub mod spectrum;
+/// colors and values that vary over surfaces
+pub mod texture;
+/// voxel grids of density for clouds and fire
+pub mod voxel;
+/// for physical things to be rendered
+pub mod world;
@@ -0,0 +1,169 @@
+use std::f32::consts::PI;
+
+use crate::{
+    light_tree::LightBounds,
+    numbers::{random, Aabb, Color, Frame, Vector},
+    spectrum::{Spectrum, Wavelengths},
+};
+
+/// lights too small or far away to be seen or hit by a path, which light the
+/// world only by being sampled directly from every surface a path reaches. the
+/// exception is triangles, which paths see as the glowing mesh they're part of
+#[derive(Clone, Debug)]
+pub enum Light {
+    /// light from a single point, `intensity` being the power per unit of solid angle
+    Point { pos: Vector, intensity: Spectrum },
+    /// a point light shining along `direction`, at full intensity out to `inner`
+    /// radians from it and fading smoothly to nothing at `outer`
+    Spot { pos: Vector, direction: Vector, intensity: Spectrum, inner: f32, outer: f32 },
+    /// light from very far away travelling along `direction`, like the sun.
+    /// `irradiance` falls on a surface facing it, spread over a disk of
+    /// `angular_radius` radians for soft shadows, or from one direction if 0
+    Directional { direction: Vector, irradiance: Spectrum, angular_radius: f32 },
+    /// one triangle of a glowing mesh, giving off `radiance` evenly from the
+    /// side its corners wind counter-clockwise around
+    Triangle { corners: [Vector; 3], radiance: Spectrum },
+}
+
+/// light reaching a point straight from a light
+#[derive(Clone, Debug)]
+pub struct Incoming {
+    /// unit direction from the point toward the light
+    pub direction: Vector,
+    /// how far away the light is, infinite for directional lights
+    pub distance: f32,
+    /// the light arriving, already divided by the chance of sampling its direction
+    pub light: Color,
+}
+
+impl Light {
+    pub fn point(pos: Vector, intensity: impl Into<Spectrum>) -> Light {
+        Light::Point { pos, intensity: intensity.into() }
+    }
+    /// a spot light with cone angles in radians from its direction
+    pub fn spot(pos: Vector, direction: Vector, intensity: impl Into<Spectrum>, inner: f32, outer: f32) -> Light {
+        Light::Spot { pos, direction: direction.unit(), intensity: intensity.into(), inner, outer: outer.max(inner) }
+    }
+    pub fn directional(direction: Vector, irradiance: impl Into<Spectrum>, angular_radius: f32) -> Light {
+        Light::Directional { direction: direction.unit(), irradiance: irradiance.into(), angular_radius }
+    }
+    pub fn triangle(corners: [Vector; 3], radiance: impl Into<Spectrum>) -> Light {
+        Light::Triangle { corners, radiance: radiance.into() }
+    }
+    /// roughly how much light it gives off in all, for choosing between lights.
+    /// directional lights light the whole scene, which fits in `scene_radius`
+    pub fn power(&self, scene_radius: f32) -> f32 {
+        match self {
+            Light::Point { intensity, .. } => 4.0 * PI * intensity.rgb().average(),
+            Light::Spot { intensity, inner, outer, .. } => {
+                // as if the falloff were halfway between the two cones
+                2.0 * PI * (1.0 - 0.5 * (inner.cos() + outer.cos())) * intensity.rgb().average()
+            }
+            Light::Directional { irradiance, .. } => PI * scene_radius * scene_radius * irradiance.rgb().average(),
+            Light::Triangle { corners, radiance } => PI * facing(corners).length() / 2.0 * radiance.rgb().average(),
+        }
+    }
+    /// where the light is and which way it shines, or None for lights infinitely far away
+    pub fn bounds(&self, scene_radius: f32) -> Option<LightBounds> {
+        let phi = self.power(scene_radius);
+        match self {
+            Light::Point { pos, .. } => Some(LightBounds::new(Aabb::new(*pos, *pos), phi, Vector::Z_POS, PI, PI / 2.0)),
+            Light::Spot { pos, direction, outer, .. } => {
+                Some(LightBounds::new(Aabb::new(*pos, *pos), phi, *direction, 0.0, *outer))
+            }
+            Light::Directional { .. } => None,
+            Light::Triangle { corners: [a, b, c], .. } => {
+                let bounds = Aabb::new(*a, *b).including(c);
+                Some(LightBounds::new(bounds, phi, facing(&[*a, *b, *c]), 0.0, PI / 2.0))
+            }
+        }
+    }
+    /// light from this light reaching `pos`, in the channels of the path, or None
+    /// if none of it gets there
+    pub fn sample(&self, pos: &Vector, wavelengths: &Wavelengths) -> Option<Incoming> {
+        match self {
+            Light::Point { pos: at, intensity } => {
+                let to = *at - *pos;
+                let distance = to.length();
+                let light = intensity.sample(wavelengths) * (distance * distance).recip();
+                Some(Incoming { direction: to / distance, distance, light })
+            }
+            Light::Spot { pos: at, direction, intensity, inner, outer } => {
+                let to = *at - *pos;
+                let distance = to.length();
+                let cos = (-to / distance).dot(direction);
+                let (cos_inner, cos_outer) = (inner.cos(), outer.cos());
+                if cos <= cos_outer {
+                    return None;
+                }
+                // smoothstep from the outer edge of the cone in to the inner one
+                let t = if cos >= cos_inner { 1.0 } else { (cos - cos_outer) / (cos_inner - cos_outer) };
+                let falloff = t * t * (3.0 - 2.0 * t);
+                let light = intensity.sample(wavelengths) * (falloff / (distance * distance));
+                Some(Incoming { direction: to / distance, distance, light })
+            }
+            Light::Directional { direction, irradiance, angular_radius } => {
+                let toward = -*direction;
+                let direction = if *angular_radius > 0.0 {
+                    // evenly over the cone of directions the disk fills
+                    let cos_max = angular_radius.cos();
+                    let cos_theta = 1.0 - random() * (1.0 - cos_max);
+                    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                    let phi = 2.0 * PI * random();
+                    let local = Vector::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+                    Frame::from_z(toward).to_world(&local)
+                } else {
+                    toward
+                };
+                Some(Incoming { direction, distance: f32::INFINITY, light: irradiance.sample(wavelengths) })
+            }
+            Light::Triangle { corners: [a, b, c], radiance } => {
+                // evenly over the area
+                let (u, v) = (random().sqrt(), random());
+                let at = *a * (1.0 - u) + *b * (u * (1.0 - v)) + *c * (u * v);
+                let to = at - *pos;
+                let distance = to.length();
+                let direction = to / distance;
+                let facing = facing(&[*a, *b, *c]);
+                let area = facing.length() / 2.0;
+                let cos = -direction.dot(&facing.unit());
+                if cos <= 0.0 {
+                    return None;
+                }
+                // from a chance per area to a chance per solid angle
+                let light = radiance.sample(wavelengths) * (cos * area / (distance * distance));
+                Some(Incoming { direction, distance, light })
+            }
+        }
+    }
+}
+
+/// the normal of the side of a triangle its corners wind counter-clockwise
+/// around, twice as long as the triangle's area
+fn facing([a, b, c]: &[Vector; 3]) -> Vector {
+    (*b - *a).cross(&(*c - *a))
+}
+
+#[test]
+fn test_light_falloff() {
+    let at = Vector::new(0.0, 2.0, 0.0);
+    let bulb = Light::point(at, Color::gray(4.0));
+    let lit = bulb.sample(&Vector::ORIGIN, &Wavelengths::Rgb).unwrap();
+    assert!((lit.light.r() - 1.0).abs() < 1e-5 && (lit.direction.y - 1.0).abs() < 1e-5 && (lit.distance - 2.0).abs() < 1e-5);
+    // a spot light pointing down lights what's under it, and nothing off to the side
+    let spot = Light::spot(at, Vector::new(0.0, -1.0, 0.0), Color::gray(4.0), 0.2, 0.4);
+    assert!((spot.sample(&Vector::ORIGIN, &Wavelengths::Rgb).unwrap().light.g() - 1.0).abs() < 1e-5);
+    assert!(spot.sample(&Vector::new(2.0, 0.0, 0.0), &Wavelengths::Rgb).is_none());
+    // directions to a sun with a size stay within its disk
+    let sun = Light::directional(Vector::new(0.0, -1.0, 0.0), Color::WHITE, 0.1);
+    for _ in 0..100 {
+        let towards = sun.sample(&Vector::ORIGIN, &Wavelengths::Rgb).unwrap().direction;
+        assert!(towards.y >= 0.1f32.cos() - 1e-5);
+    }
+    // a small glowing triangle facing down is much like a bulb of its radiance
+    // times its area, and gives nothing to what's behind it
+    let corners = [Vector::new(0.0, 2.0, 0.0), Vector::new(0.1, 2.0, 0.0), Vector::new(0.0, 2.0, 0.1)];
+    let glow = Light::triangle(corners, Color::gray(800.0));
+    assert!((glow.sample(&Vector::ORIGIN, &Wavelengths::Rgb).unwrap().light.g() - 1.0).abs() < 0.01);
+    assert!(glow.sample(&Vector::new(0.0, 3.0, 0.0), &Wavelengths::Rgb).is_none());
+}
@@ -0,0 +1,362 @@
+use std::f32::consts::PI;
+
+use crate::{
+    light::Light,
+    numbers::{random, Aabb, Transform, Vector},
+};
+
+/// how the lights to sample are chosen, from the world's point, spot and
+/// directional lights and the triangles of its glowing meshes
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum LightSampling {
+    /// every light as often as every other
+    Uniform,
+    /// brighter lights more often
+    Power,
+    /// lights more often the more they could light the point, by power, distance
+    /// and which way they face, found through a tree of lights
+    #[default]
+    Tree,
+}
+
+/// picks from a fixed set of choices in proportion to their weights in constant
+/// time, by vose's alias method. each slot keeps one choice with some chance and
+/// passes the rest of its share on to another
+pub struct AliasTable {
+    /// chance of keeping each slot's own choice, and the choice taken otherwise
+    slots: Vec<(f32, usize)>,
+    /// each choice's share of the total weight
+    pmf: Vec<f32>,
+}
+
+impl AliasTable {
+    /// None if there's nothing to choose or no weight to choose by
+    pub fn new(weights: &[f32]) -> Option<AliasTable> {
+        let total: f32 = weights.iter().map(|w| w.max(0.0)).sum();
+        if weights.is_empty() || total <= 0.0 || !total.is_finite() {
+            return None;
+        }
+        let pmf: Vec<f32> = weights.iter().map(|w| w.max(0.0) / total).collect();
+        let count = weights.len();
+        let mut scaled: Vec<f32> = pmf.iter().map(|p| p * count as f32).collect();
+        let mut slots = vec![(1.0, 0); count];
+        let (mut small, mut large): (Vec<usize>, Vec<usize>) = (0..count).partition(|&i| scaled[i] < 1.0);
+        while let (Some(&s), Some(&l)) = (small.last(), large.last()) {
+            small.pop();
+            slots[s] = (scaled[s], l);
+            scaled[l] -= 1.0 - scaled[s];
+            if scaled[l] < 1.0 {
+                large.pop();
+                small.push(l);
+            }
+        }
+        // whatever's left over is full, up to rounding
+        for i in small.into_iter().chain(large) {
+            slots[i] = (1.0, i);
+        }
+        Some(AliasTable { slots, pmf })
+    }
+    /// a choice and its chance
+    pub fn sample(&self) -> (usize, f32) {
+        let count = self.slots.len();
+        let u = random() * count as f32;
+        let slot = (u as usize).min(count - 1);
+        let (keep, alias) = self.slots[slot];
+        let index = if u - (slot as f32) < keep { slot } else { alias };
+        (index, self.pmf[index])
+    }
+    pub fn pmf(&self, index: usize) -> f32 {
+        self.pmf[index]
+    }
+}
+
+/// where some lights are, how much light they give off, and the directions they
+/// give it off in: every light's axis is within `theta_o` of `axis`, and each
+/// gives off light up to `theta_e` beyond its own axis
+#[derive(Clone, Debug)]
+pub struct LightBounds {
+    pub bounds: Aabb,
+    pub phi: f32,
+    pub axis: Vector,
+    pub theta_o: f32,
+    pub theta_e: f32,
+}
+
+/// the angle between two unit vectors
+fn angle_between(a: &Vector, b: &Vector) -> f32 {
+    a.dot(b).clamp(-1.0, 1.0).acos()
+}
+
+impl LightBounds {
+    pub fn new(bounds: Aabb, phi: f32, axis: Vector, theta_o: f32, theta_e: f32) -> LightBounds {
+        LightBounds { bounds, phi, axis: axis.unit(), theta_o, theta_e }
+    }
+    /// bounds around the lights of both
+    pub fn union(&self, other: &LightBounds) -> LightBounds {
+        if self.phi <= 0.0 {
+            return other.clone();
+        }
+        if other.phi <= 0.0 {
+            return self.clone();
+        }
+        let (axis, theta_o) = union_cones(&self.axis, self.theta_o, &other.axis, other.theta_o);
+        LightBounds {
+            bounds: self.bounds.union(&other.bounds),
+            phi: self.phi + other.phi,
+            axis,
+            theta_o,
+            theta_e: self.theta_e.max(other.theta_e),
+        }
+    }
+    /// a guess at how much these lights could light `pos`, by their power over
+    /// the squared distance and the angles they could possibly shine on it at.
+    /// `normal` is left out in media, where light can come from any direction
+    pub fn importance(&self, pos: &Vector, normal: Option<&Vector>) -> f32 {
+        let center = self.bounds.center();
+        let radius = (self.bounds.max - self.bounds.min).length() / 2.0;
+        let to_pos = *pos - center;
+        let d2 = to_pos.square_length().max(radius).max(1e-8);
+        // the angles the bounds take up seen from pos, and from the axis to pos
+        let theta_b = if to_pos.square_length() <= radius * radius {
+            PI
+        } else {
+            (radius / to_pos.length()).clamp(0.0, 1.0).asin()
+        };
+        let theta_w = if to_pos.near_zero() { 0.0 } else { angle_between(&self.axis, &to_pos.unit()) };
+        // the smallest angle any light's axis could make with the direction to pos
+        let theta_p = (theta_w - self.theta_o - theta_b).max(0.0);
+        if theta_p >= self.theta_e {
+            return 0.0;
+        }
+        let mut importance = self.phi * theta_p.cos() / d2;
+        if let Some(normal) = normal {
+            // light could arrive from either side, through glass
+            let theta_i = if to_pos.near_zero() { 0.0 } else { angle_between(normal, &-to_pos.unit()) };
+            let theta_i = theta_i.min(PI - theta_i);
+            importance *= (theta_i - theta_b).max(0.0).cos();
+        }
+        importance.max(0.0)
+    }
+}
+
+/// the narrowest cone around two cones of directions, as an axis and a spread
+fn union_cones(a: &Vector, theta_a: f32, b: &Vector, theta_b: f32) -> (Vector, f32) {
+    let theta_d = angle_between(a, b);
+    if (theta_d + theta_b).min(PI) <= theta_a {
+        return (*a, theta_a);
+    }
+    if (theta_d + theta_a).min(PI) <= theta_b {
+        return (*b, theta_b);
+    }
+    let theta_o = (theta_a + theta_d + theta_b) / 2.0;
+    if theta_o >= PI {
+        return (*a, PI);
+    }
+    // turn a toward b until the cone just reaches both edges
+    let turn = a.cross(b);
+    if turn.square_length() < 1e-12 {
+        return (*a, PI);
+    }
+    let axis = Transform::rotate(turn, theta_o - theta_a).vector(a);
+    (axis.unit(), theta_o)
+}
+
+enum Node {
+    Leaf { bounds: LightBounds, light: usize },
+    Branch { bounds: LightBounds, left: usize, right: usize },
+}
+
+impl Node {
+    fn bounds(&self) -> &LightBounds {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Branch { bounds, .. } => bounds,
+        }
+    }
+}
+
+/// a tree over lights with a position, walked from the root down by choosing
+/// each time between two branches by how much they could light a point
+pub struct LightTree {
+    nodes: Vec<Node>,
+    /// for each light, the turns from the root to its leaf, one bit per level
+    /// with right set, and the number of levels
+    trails: Vec<Option<(u64, u32)>>,
+}
+
+impl LightTree {
+    /// builds a tree over the lights with bounds, splitting at the median of
+    /// the longest axis as the bvh does
+    pub fn new(bounds: &[Option<LightBounds>]) -> LightTree {
+        let mut tree = LightTree { nodes: Vec::new(), trails: vec![None; bounds.len()] };
+        let mut lights: Vec<usize> = (0..bounds.len()).filter(|&i| bounds[i].as_ref().is_some_and(|b| b.phi > 0.0)).collect();
+        if !lights.is_empty() {
+            tree.build(bounds, &mut lights, 0, 0);
+        }
+        tree
+    }
+    fn build(&mut self, bounds: &[Option<LightBounds>], lights: &mut [usize], trail: u64, depth: u32) -> usize {
+        let index = self.nodes.len();
+        let of = |i: usize| bounds[i].as_ref().unwrap();
+        // splitting at the median halves the lights each level, so trails never
+        // need more bits than a u64 has
+        if lights.len() == 1 {
+            let light = lights[0];
+            self.trails[light] = Some((trail, depth));
+            self.nodes.push(Node::Leaf { bounds: of(light).clone(), light });
+            return index;
+        }
+        let total = lights[1..].iter().fold(of(lights[0]).clone(), |b, &i| b.union(of(i)));
+        let centers = lights.iter().fold(Aabb::EMPTY, |b, &i| b.including(&of(i).bounds.center()));
+        let axis = centers.longest_axis();
+        let middle = lights.len() / 2;
+        lights.select_nth_unstable_by(middle, |&a, &b| {
+            of(a).bounds.center().axis(axis).total_cmp(&of(b).bounds.center().axis(axis))
+        });
+        // placeholder until both children exist
+        self.nodes.push(Node::Leaf { bounds: total.clone(), light: lights[0] });
+        let (low, high) = lights.split_at_mut(middle);
+        let left = self.build(bounds, low, trail, depth + 1);
+        let right = self.build(bounds, high, trail | 1 << depth, depth + 1);
+        self.nodes[index] = Node::Branch { bounds: total, left, right };
+        index
+    }
+    /// the chance of taking the right branch of a node for a point
+    fn right_chance(&self, left: usize, right: usize, pos: &Vector, normal: Option<&Vector>) -> Option<f32> {
+        let left = self.nodes[left].bounds().importance(pos, normal);
+        let right = self.nodes[right].bounds().importance(pos, normal);
+        (left + right > 0.0).then(|| right / (left + right))
+    }
+    /// a light and its chance of being picked, or None if no light could light the point
+    pub fn sample(&self, pos: &Vector, normal: Option<&Vector>) -> Option<(usize, f32)> {
+        let mut index = 0;
+        let mut chance = 1.0;
+        loop {
+            match self.nodes.get(index)? {
+                Node::Leaf { light, bounds } => return (bounds.importance(pos, normal) > 0.0).then_some((*light, chance)),
+                Node::Branch { left, right, .. } => {
+                    let right_chance = self.right_chance(*left, *right, pos, normal)?;
+                    if random() < right_chance {
+                        chance *= right_chance;
+                        index = *right;
+                    } else {
+                        chance *= 1.0 - right_chance;
+                        index = *left;
+                    }
+                }
+            }
+        }
+    }
+    /// the chance of `sample` picking a light for a point
+    pub fn pmf(&self, light: usize, pos: &Vector, normal: Option<&Vector>) -> f32 {
+        let Some((trail, depth)) = self.trails[light] else { return 0.0 };
+        let mut index = 0;
+        let mut chance = 1.0;
+        for level in 0..depth {
+            let Node::Branch { left, right, .. } = &self.nodes[index] else { return 0.0 };
+            let Some(right_chance) = self.right_chance(*left, *right, pos, normal) else { return 0.0 };
+            if trail & (1 << level) != 0 {
+                chance *= right_chance;
+                index = *right;
+            } else {
+                chance *= 1.0 - right_chance;
+                index = *left;
+            }
+        }
+        if self.nodes[index].bounds().importance(pos, normal) > 0.0 {
+            chance
+        } else {
+            0.0
+        }
+    }
+}
+
+/// chooses which of a world's lights to sample for a point
+pub enum LightPicker {
+    Uniform(usize),
+    Power(Option<AliasTable>),
+    /// lights infinitely far away have no place in the tree, so they're
+    /// picked evenly alongside it as if it were one more light
+    Tree { tree: LightTree, infinite: Vec<usize> },
+}
+
+impl LightPicker {
+    pub fn new(lights: &[Light], sampling: LightSampling, scene_radius: f32) -> LightPicker {
+        match sampling {
+            LightSampling::Uniform => LightPicker::Uniform(lights.len()),
+            LightSampling::Power => {
+                let powers: Vec<f32> = lights.iter().map(|light| light.power(scene_radius)).collect();
+                LightPicker::Power(AliasTable::new(&powers))
+            }
+            LightSampling::Tree => {
+                let bounds: Vec<Option<LightBounds>> = lights.iter().map(|light| light.bounds(scene_radius)).collect();
+                let infinite = (0..lights.len()).filter(|&i| bounds[i].is_none()).collect();
+                LightPicker::Tree { tree: LightTree::new(&bounds), infinite }
+            }
+        }
+    }
+    /// a light to sample for a point and its chance of being picked
+    pub fn pick(&self, pos: &Vector, normal: Option<&Vector>) -> Option<(usize, f32)> {
+        match self {
+            LightPicker::Uniform(0) => None,
+            LightPicker::Uniform(count) => {
+                let index = ((random() * *count as f32) as usize).min(count - 1);
+                Some((index, 1.0 / *count as f32))
+            }
+            LightPicker::Power(table) => table.as_ref().map(|table| table.sample()),
+            LightPicker::Tree { tree, infinite } => {
+                let choices = infinite.len() + if tree.nodes.is_empty() { 0 } else { 1 };
+                if choices == 0 {
+                    return None;
+                }
+                let choice = ((random() * choices as f32) as usize).min(choices - 1);
+                let chance = 1.0 / choices as f32;
+                match infinite.get(choice) {
+                    Some(&light) => Some((light, chance)),
+                    None => tree.sample(pos, normal).map(|(light, within)| (light, chance * within)),
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_alias_table_matches_weights() {
+    use crate::numbers::reseed;
+    reseed(7);
+    let weights = [1.0, 0.0, 3.0, 0.5, 2.5];
+    let table = AliasTable::new(&weights).unwrap();
+    let runs = 70000;
+    let mut counts = [0; 5];
+    for _ in 0..runs {
+        let (index, pmf) = table.sample();
+        assert!((pmf - table.pmf(index)).abs() < 1e-6);
+        counts[index] += 1;
+    }
+    for (i, w) in weights.iter().enumerate() {
+        assert!((counts[i] as f32 / runs as f32 - w / 7.0).abs() < 0.01);
+    }
+    assert!(AliasTable::new(&[0.0, 0.0]).is_none());
+}
+
+#[test]
+fn test_light_tree_prefers_nearby_lights() {
+    use crate::{numbers::{reseed, Color}, light::Light};
+    reseed(8);
+    let mut lights: Vec<Light> = (0..40)
+        .map(|i| Light::point(Vector::new(i as f32, 1.0, 0.0), Color::WHITE))
+        .collect();
+    // a spot pointing away from the point can't light it
+    lights.push(Light::spot(Vector::new(0.0, 1.0, 0.5), Vector::new(0.0, 1.0, 0.0), Color::gray(100.0), 0.2, 0.3));
+    let bounds: Vec<_> = lights.iter().map(|light| light.bounds(1.0)).collect();
+    let tree = LightTree::new(&bounds);
+    let pos = Vector::ORIGIN;
+    let normal = Vector::new(0.0, 1.0, 0.0);
+    let total: f32 = (0..lights.len()).map(|i| tree.pmf(i, &pos, Some(&normal))).sum();
+    assert!((total - 1.0).abs() < 1e-4);
+    assert!(tree.pmf(0, &pos, Some(&normal)) > 10.0 * tree.pmf(39, &pos, Some(&normal)));
+    assert!(tree.pmf(40, &pos, Some(&normal)) == 0.0);
+    for _ in 0..1000 {
+        let (light, chance) = tree.sample(&pos, Some(&normal)).unwrap();
+        assert!((chance - tree.pmf(light, &pos, Some(&normal))).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,321 @@
+use std::collections::HashMap;
+
+use crate::ray::{Lobe, PathEvent};
+
+/// how many kinds of event an expression tells apart: the camera, volumes,
+/// lights, the background, glowing media, then reflection and transmission
+/// each diffuse, glossy and specular
+const SYMBOLS: usize = 11;
+const ALL: u16 = (1 << SYMBOLS) - 1;
+const REFLECT: u16 = 0b111 << 5;
+const TRANSMIT: u16 = 0b111 << 8;
+/// most states an expression compiles to. some short expressions, like `.*D`
+/// followed by many `.`, need exponentially many
+const MAX_STATES: usize = 4096;
+
+/// which kind of event each is, as a bit in a set of them
+fn symbol(event: &PathEvent) -> usize {
+    match event {
+        PathEvent::Camera => 0,
+        PathEvent::Volume => 1,
+        PathEvent::Light => 2,
+        PathEvent::Background => 3,
+        PathEvent::Emission => 4,
+        PathEvent::Surface { lobe, through } => {
+            let lobe = match lobe {
+                Lobe::Diffuse => 0,
+                Lobe::Glossy => 1,
+                Lobe::Specular => 2,
+            };
+            5 + 3 * *through as usize + lobe
+        }
+    }
+}
+
+/// the events a letter stands for on its own, or in the first or second
+/// place between angle brackets
+fn letter(c: char, place: Option<usize>) -> Result<u16, String> {
+    let scattering = |lobe: u16| (1 << (5 + lobe)) | (1 << (8 + lobe));
+    Ok(match (c, place) {
+        ('.', _) => ALL,
+        ('C', None | Some(0)) => 1,
+        ('V', None | Some(0)) => 1 << 1,
+        ('L', None | Some(0)) => 1 << 2,
+        ('B', None | Some(0)) => 1 << 3,
+        ('O', None | Some(0)) => 1 << 4,
+        ('R', None | Some(0)) => REFLECT,
+        ('T', None | Some(0)) => TRANSMIT,
+        ('D', None | Some(1)) => scattering(0),
+        ('G', None | Some(1)) => scattering(1),
+        ('S', None | Some(1)) => scattering(2),
+        _ => return Err(format!("unexpected {}", c)),
+    })
+}
+
+/// an expression read but not yet compiled
+enum Node {
+    /// any one event in the set
+    Events(u16),
+    Sequence(Vec<Node>),
+    Either(Vec<Node>),
+    /// any number of times, none included
+    Repeat(Box<Node>),
+    /// at least once
+    Several(Box<Node>),
+    Maybe(Box<Node>),
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn peek(&mut self) -> Option<char> {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+        self.chars.peek().copied()
+    }
+    fn next(&mut self) -> Result<char, String> {
+        self.peek();
+        self.chars.next().ok_or("expression ends too soon".to_string())
+    }
+    fn expect(&mut self, wanted: char) -> Result<(), String> {
+        match self.next()? {
+            c if c == wanted => Ok(()),
+            c => Err(format!("expected {} but found {}", wanted, c)),
+        }
+    }
+    fn either(&mut self) -> Result<Node, String> {
+        let mut choices = vec![self.sequence()?];
+        while self.peek() == Some('|') {
+            self.next()?;
+            choices.push(self.sequence()?);
+        }
+        Ok(if choices.len() == 1 { choices.pop().unwrap() } else { Node::Either(choices) })
+    }
+    fn sequence(&mut self) -> Result<Node, String> {
+        let mut nodes = Vec::new();
+        while !matches!(self.peek(), None | Some('|' | ')')) {
+            nodes.push(self.repeated()?);
+        }
+        Ok(Node::Sequence(nodes))
+    }
+    fn repeated(&mut self) -> Result<Node, String> {
+        let mut node = self.atom()?;
+        loop {
+            node = match self.peek() {
+                Some('*') => Node::Repeat(Box::new(node)),
+                Some('+') => Node::Several(Box::new(node)),
+                Some('?') => Node::Maybe(Box::new(node)),
+                _ => return Ok(node),
+            };
+            self.next()?;
+        }
+    }
+    fn atom(&mut self) -> Result<Node, String> {
+        match self.next()? {
+            '(' => {
+                let node = self.either()?;
+                self.expect(')')?;
+                Ok(node)
+            }
+            '<' => {
+                let kind = self.events(Some(0))?;
+                let scattering = self.events(Some(1))?;
+                self.expect('>')?;
+                Ok(Node::Events(kind & scattering))
+            }
+            '[' => Ok(Node::Events(self.set(None)?)),
+            c => Ok(Node::Events(letter(c, None)?)),
+        }
+    }
+    /// a letter or a set of them, in a place between angle brackets
+    fn events(&mut self, place: Option<usize>) -> Result<u16, String> {
+        match self.next()? {
+            '[' => self.set(place),
+            c => letter(c, place),
+        }
+    }
+    /// the letters of a set up to its closing bracket, all but them after a ^
+    fn set(&mut self, place: Option<usize>) -> Result<u16, String> {
+        let negated = self.chars.next_if_eq(&'^').is_some();
+        let mut events = 0;
+        loop {
+            match self.next()? {
+                ']' => break,
+                c => events |= letter(c, place)?,
+            }
+        }
+        Ok(if negated { ALL & !events } else { events })
+    }
+}
+
+/// a state of the automaton built straight from the expression, with one
+/// step on a set of events and any number of steps that take no event
+#[derive(Default)]
+struct Step {
+    on: Option<(u16, usize)>,
+    free: Vec<usize>,
+}
+
+/// builds the states for `node` starting at `from`, returning where it ends
+fn build(states: &mut Vec<Step>, node: &Node, from: usize) -> usize {
+    let new = |states: &mut Vec<Step>| {
+        states.push(Step::default());
+        states.len() - 1
+    };
+    match node {
+        Node::Events(events) => {
+            let to = new(states);
+            let at = new(states);
+            states[from].free.push(at);
+            states[at].on = Some((*events, to));
+            to
+        }
+        Node::Sequence(nodes) => nodes.iter().fold(from, |at, node| build(states, node, at)),
+        Node::Either(choices) => {
+            let end = new(states);
+            for choice in choices {
+                let start = new(states);
+                states[from].free.push(start);
+                let at = build(states, choice, start);
+                states[at].free.push(end);
+            }
+            end
+        }
+        Node::Repeat(node) => {
+            let start = new(states);
+            states[from].free.push(start);
+            let at = build(states, node, start);
+            states[at].free.push(start);
+            start
+        }
+        Node::Several(node) => {
+            let start = new(states);
+            states[from].free.push(start);
+            let at = build(states, node, start);
+            let end = new(states);
+            states[at].free.extend([start, end]);
+            end
+        }
+        Node::Maybe(node) => {
+            let start = new(states);
+            states[from].free.push(start);
+            let at = build(states, node, start);
+            let end = new(states);
+            states[start].free.push(end);
+            states[at].free.push(end);
+            end
+        }
+    }
+}
+
+/// every state reachable from these without taking an event, in order
+fn closure(states: &[Step], from: impl IntoIterator<Item = usize>) -> Vec<usize> {
+    let mut reached = vec![false; states.len()];
+    let mut stack: Vec<usize> = from.into_iter().collect();
+    while let Some(state) = stack.pop() {
+        if !reached[state] {
+            reached[state] = true;
+            stack.extend(&states[state].free);
+        }
+    }
+    (0..states.len()).filter(|&s| reached[s]).collect()
+}
+
+/// a light path expression, matching the events of whole paths from the
+/// camera to where their light came from, like `C<RD>L` for light from a
+/// light reflected once diffusely, or `CS+[LB]` for light seen only in mirrors.
+///
+/// events are written as the letters of open shading language: `C` the camera,
+/// `R` and `T` reflection and transmission at a surface, which is `D`iffuse,
+/// `G`lossy or `S`pecular, `V` scattering in a volume, `L` a light, `B` the
+/// background and `O` a glowing medium. `<RD>` names both parts of a surface
+/// event, where a letter alone leaves the other part open, and `.` is any event.
+/// `[...]` and `[^...]` are sets of events, and `|`, `*`, `+`, `?` and
+/// parentheses work as in regular expressions
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lpe {
+    source: String,
+    /// the next state for each state and kind of event, compiled to a
+    /// deterministic automaton so matching only ever follows one path
+    next: Vec<[Option<u32>; SYMBOLS]>,
+    accepting: Vec<bool>,
+}
+
+impl Lpe {
+    pub fn new(expression: &str) -> Result<Lpe, String> {
+        let mut parser = Parser { chars: expression.chars().peekable() };
+        let node = parser.either()?;
+        if let Some(c) = parser.peek() {
+            return Err(format!("unexpected {}", c));
+        }
+        let mut states = vec![Step::default()];
+        let end = build(&mut states, &node, 0);
+        // each state of the automaton is a set of the states built above
+        let mut sets = vec![closure(&states, [0])];
+        let mut known = HashMap::from([(sets[0].clone(), 0)]);
+        let mut next = Vec::new();
+        let mut at = 0;
+        while at < sets.len() {
+            let mut row = [None; SYMBOLS];
+            for (symbol, slot) in row.iter_mut().enumerate() {
+                let targets = sets[at].iter().filter_map(|&s| match states[s].on {
+                    Some((events, to)) if events & (1 << symbol) != 0 => Some(to),
+                    _ => None,
+                });
+                let set = closure(&states, targets);
+                if set.is_empty() {
+                    continue;
+                }
+                let index = *known.entry(set.clone()).or_insert_with(|| {
+                    sets.push(set);
+                    sets.len() - 1
+                });
+                if sets.len() > MAX_STATES {
+                    return Err("expression too complex".to_string());
+                }
+                *slot = Some(index as u32);
+            }
+            next.push(row);
+            at += 1;
+        }
+        let accepting = sets.iter().map(|set| set.contains(&end)).collect();
+        Ok(Lpe { source: expression.to_string(), next, accepting })
+    }
+    /// the expression as it was written
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+    /// whether the whole of a path's events fit the expression
+    pub fn matches(&self, events: &[PathEvent]) -> bool {
+        let mut state = 0;
+        for event in events {
+            match self.next[state][symbol(event)] {
+                Some(to) => state = to as usize,
+                None => return false,
+            }
+        }
+        self.accepting[state]
+    }
+}
+
+#[test]
+fn test_expressions_match_paths() {
+    use PathEvent::*;
+    let surface = |lobe, through| Surface { lobe, through };
+    let diffuse = surface(Lobe::Diffuse, false);
+    let mirror = surface(Lobe::Specular, false);
+    let glass = surface(Lobe::Specular, true);
+    let direct = Lpe::new("C<RD>L").unwrap();
+    assert!(direct.matches(&[Camera, diffuse, Light]));
+    assert!(!direct.matches(&[Camera, diffuse, diffuse, Light]) && !direct.matches(&[Camera, mirror, Light]));
+    let caustic = Lpe::new("C D S+ [LB]").unwrap();
+    assert!(caustic.matches(&[Camera, diffuse, glass, mirror, Background]));
+    assert!(!caustic.matches(&[Camera, diffuse, Background]));
+    let anything_but_volumes = Lpe::new("C[^V]*(L|B)").unwrap();
+    assert!(anything_but_volumes.matches(&[Camera, glass, diffuse, Light]));
+    assert!(!anything_but_volumes.matches(&[Camera, Volume, Light]));
+    assert!(Lpe::new("C<T.>?.*O").unwrap().matches(&[Camera, Volume, Emission]));
+    assert!(Lpe::new("C<DR>L").is_err() && Lpe::new("C(RL").is_err() && Lpe::new("CX").is_err());
+    assert!(Lpe::new(&format!("C.*D{}", ".".repeat(20))).is_err());
+}
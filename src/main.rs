@@ -1,50 +1,245 @@
-use indicatif::ProgressBar;
-use rayon::prelude::*;
-
-/// handling for view transforms
-mod camera;
-/// image buffer
-mod image;
-/// numerical primatives like Vector
-mod numbers;
-/// main ray casting functionality
-mod ray;
-/// for physical things to be rendered
-mod world;
-/// physical materials for meshes
-mod material;
-
-use crate::{numbers::Samples, world::World, camera::*, image::*};
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use indicatif::{ProgressBar, ProgressStyle};
+use weekend_raytrace::{
+    aov::{Aov, AovFilm},
+    camera::Camera,
+    checkpoint::Checkpoint,
+    integrator::Integrator,
+    render::{RenderSettings, Renderer},
+    world::World,
+};
+
+/// stopping a render early on ctrl-c
+mod interrupt;
 
 const WIDTH: usize = 1024;
 const HEIGHT: usize = WIDTH / 16 * 9;
 const PIXEL_COUNT: usize = HEIGHT * WIDTH;
 const SAMPLES: usize = 100;
+const PASS_SAMPLES: usize = 10;
 const MAX_BOUNCES: usize = 1000;
+/// where a render that stops early saves its progress if no checkpoint file was given
+const DEFAULT_CHECKPOINT: &str = "weekend-raytrace.checkpoint";
+
+const USAGE: &str = "usage: weekend-raytrace [--samples N] [--pass-samples N] [--seed N] \
+[--checkpoint FILE] [--checkpoint-every PASSES] [--resume FILE] [--time-limit SECONDS] [--spectral] \
+[--integrator path|ao[:RADIUS]|normals|depth[:FAR]|uv|material|barycentrics|bounces[:MOST]] \
+[--aov albedo|normal|depth|direct-diffuse|indirect-diffuse|specular|emission|object-id|material-id]... [--lpe NAME=EXPRESSION]...";
+
+/// settings read from the command line
+struct Options {
+    /// total samples per pixel, rounded up to whole passes
+    samples: usize,
+    /// samples per pass and seed if given, which a resumed render takes from
+    /// its checkpoint instead
+    samples_per_pass: Option<usize>,
+    seed: Option<u64>,
+    /// where to write checkpoints, if anywhere
+    checkpoint: Option<PathBuf>,
+    /// number of passes between checkpoints
+    checkpoint_every: usize,
+    /// checkpoint to continue from
+    resume: Option<PathBuf>,
+    /// wall-clock budget for this run, checked between passes
+    time_limit: Option<Duration>,
+    /// trace wavelengths rather than rgb
+    spectral: bool,
+    integrator: Integrator,
+    /// render passes to save as NAME.pfm alongside the picture
+    aovs: Vec<Aov>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options {
+            samples: SAMPLES,
+            samples_per_pass: None,
+            seed: None,
+            checkpoint: None,
+            checkpoint_every: 1,
+            resume: None,
+            time_limit: None,
+            spectral: false,
+            integrator: Integrator::Path,
+            aovs: Vec::new(),
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--samples" => options.samples = parse_number(&value()?)?,
+                "--pass-samples" => options.samples_per_pass = Some(parse_number(&value()?)?),
+                "--seed" => options.seed = Some(parse_number(&value()?)?),
+                "--checkpoint" => options.checkpoint = Some(value()?.into()),
+                "--checkpoint-every" => options.checkpoint_every = parse_number(&value()?)?,
+                "--resume" => options.resume = Some(value()?.into()),
+                "--spectral" => options.spectral = true,
+                "--integrator" => options.integrator = parse_integrator(&value()?)?,
+                "--aov" => {
+                    let name = value()?;
+                    options.aovs.push(Aov::from_name(&name).ok_or(format!("unknown aov {}", name))?);
+                }
+                "--lpe" => {
+                    let pass = value()?;
+                    let (name, expression) = pass.split_once('=').ok_or(format!("expected NAME=EXPRESSION, not {}", pass))?;
+                    let aov = Aov::custom(name, expression).map_err(|e| format!("{} in {}", e, pass))?;
+                    if options.aovs.iter().any(|other| other.name() == name) {
+                        return Err(format!("more than one pass named {}", name));
+                    }
+                    options.aovs.push(aov);
+                }
+                "--time-limit" => {
+                    let seconds: f64 = parse_number(&value()?)?;
+                    let limit = Duration::try_from_secs_f64(seconds)
+                        .map_err(|_| format!("{} is not a valid time limit", seconds))?;
+                    options.time_limit = Some(limit);
+                }
+                _ => return Err(format!("unknown argument {}", arg)),
+            }
+        }
+        if options.samples_per_pass == Some(0) || options.checkpoint_every == 0 {
+            return Err("pass sizes must be at least 1".to_string());
+        }
+        if options.resume.is_some() && (options.seed.is_some() || options.samples_per_pass.is_some()) {
+            return Err("a resumed render keeps the seed and pass size of its checkpoint".to_string());
+        }
+        // checkpoints only keep the picture, so passes would miss the samples before it
+        if options.resume.is_some() && !options.aovs.is_empty() {
+            return Err("render passes can't be resumed, only the picture".to_string());
+        }
+        Ok(options)
+    }
+}
 
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("{} is not a valid number", value))
+}
+
+/// an integrator by name, with an optional number after a colon for those that take one
+fn parse_integrator(value: &str) -> Result<Integrator, String> {
+    let (name, number) = match value.split_once(':') {
+        Some((name, number)) => (name, Some(number)),
+        None => (value, None),
+    };
+    Ok(match name {
+        "path" => Integrator::Path,
+        "ao" => Integrator::AmbientOcclusion { radius: number.map_or(Ok(1.0), parse_number)? },
+        "normals" => Integrator::Normals,
+        "depth" => Integrator::Depth { far: number.map_or(Ok(10.0), parse_number)? },
+        "uv" => Integrator::Uv,
+        "material" => Integrator::MaterialId,
+        "barycentrics" => Integrator::Barycentrics,
+        "bounces" => Integrator::Bounces { most: number.map_or(Ok(16), parse_number)? },
+        _ => return Err(format!("unknown integrator {}", value)),
+    })
+}
 
 fn main() {
-    let bar = ProgressBar::new(PIXEL_COUNT as u64);
-    let mut buffer = ImageBuffer::new(WIDTH, HEIGHT);
-    let camera = Camera::new(2.0 * (16.0 / 9.0), 2.0);
+    let options = Options::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        std::process::exit(2);
+    });
+    let renderer = Renderer::new(RenderSettings {
+        width: WIDTH,
+        height: HEIGHT,
+        samples: options.samples,
+        samples_per_pass: options.samples_per_pass.unwrap_or(PASS_SAMPLES),
+        max_bounces: MAX_BOUNCES,
+        seed: options.seed.unwrap_or(0),
+        spectral: options.spectral,
+        integrator: options.integrator,
+    });
+    let mut state = match &options.resume {
+        Some(path) => {
+            let checkpoint = Checkpoint::load(path).unwrap_or_else(|e| {
+                eprintln!("could not resume from {}: {}", path.display(), e);
+                std::process::exit(1);
+            });
+            if checkpoint.film.width() != WIDTH || checkpoint.film.height() != HEIGHT {
+                eprintln!("checkpoint {} was made at a different resolution", path.display());
+                std::process::exit(1);
+            }
+            if checkpoint.spectral != options.spectral {
+                let mode = if checkpoint.spectral { "with" } else { "without" };
+                eprintln!("checkpoint {} was made {} --spectral", path.display(), mode);
+                std::process::exit(1);
+            }
+            checkpoint
+        }
+        None => renderer.start(),
+    };
+    // keep checkpointing to the file we resumed from unless told otherwise
+    let checkpoint_path = options.checkpoint.clone().or_else(|| options.resume.clone());
+    let total_passes = renderer.total_passes(&state);
 
+    let camera = Camera::new(2.0 * (16.0 / 9.0), 2.0);
     let world = World::new();
 
-    let pixels = camera
-        .rays(WIDTH, HEIGHT, SAMPLES)
-        .map(|(_uv, rays)| {
-            let samples: Samples = rays.par_iter()
-                .map(|ray| {
-                    ray.cast(&world, MAX_BOUNCES).sample()
-                })
-                .sum();
-            let color = samples.to_color();
-            bar.inc(1);
-            color.to_pixel()
-        })
-        .collect();
-    buffer.swap_pixels(pixels);
-    bar.finish();
-    let out_string = buffer.serialize_ppm();
+    let mut aovs = AovFilm::new(WIDTH, HEIGHT, &options.aovs);
+
+    interrupt::install();
+    let started = Instant::now();
+    let first_pass = state.passes;
+    let remaining = total_passes.saturating_sub(state.passes) as usize;
+    let bar = ProgressBar::new((PIXEL_COUNT * remaining) as u64);
+    if options.time_limit.is_some() {
+        bar.set_style(ProgressStyle::default_bar().template("{wide_bar} {pos}/{len} {msg}"));
+    }
+    let mut stopped = None;
+    while state.passes < total_passes {
+        if interrupt::requested() {
+            stopped = Some("interrupted");
+            break;
+        }
+        if let Some(limit) = options.time_limit {
+            // stop early rather than start a pass we expect to overrun the budget
+            let elapsed = started.elapsed();
+            let done = state.passes - first_pass;
+            let per_pass = if done > 0 { elapsed / done } else { Duration::ZERO };
+            if elapsed + per_pass > limit {
+                stopped = Some("out of time");
+                break;
+            }
+            bar.set_message(format!("{}s left", (limit - elapsed).as_secs()));
+        }
+        renderer.render_pass_with_aovs(&world, &camera, &mut state, &mut aovs, || bar.inc(1));
+        if let Some(path) = &checkpoint_path {
+            if (state.passes as usize).is_multiple_of(options.checkpoint_every) || state.passes == total_passes {
+                if let Err(e) = state.save(path) {
+                    eprintln!("could not write checkpoint {}: {}", path.display(), e);
+                }
+            }
+        }
+    }
+    match stopped {
+        Some(reason) => {
+            bar.abandon_with_message(format!("{} after {} of {} passes", reason, state.passes, total_passes));
+            // without a finished pass there's nothing to resume
+            if state.passes > 0 {
+                let path = checkpoint_path.unwrap_or_else(|| DEFAULT_CHECKPOINT.into());
+                match state.save(&path) {
+                    Ok(()) => eprintln!("resume with --resume {}", path.display()),
+                    Err(e) => eprintln!("could not write checkpoint {}: {}", path.display(), e),
+                }
+            }
+        }
+        None => bar.finish(),
+    }
+    // a film without a single sample has no picture in it to write
+    if state.passes == first_pass && options.resume.is_none() {
+        eprintln!("stopped before the first pass finished, nothing to write");
+        std::process::exit(1);
+    }
+
+    for (aov, image) in aovs.to_images() {
+        let path = PathBuf::from(format!("{}.pfm", aov.name()));
+        if let Err(e) = image.save_pfm(&path) {
+            eprintln!("could not write {}: {}", path.display(), e);
+        }
+    }
+    let out_string = state.film.to_image().serialize_ppm();
     println!("{}", out_string);
 }
@@ -1,10 +1,143 @@
-use crate::{numbers::{Color, Vector}, ray::{Ray, Hit, Bounce}};
-use rand::Rng;
+use std::{
+    f32::consts::PI,
+    hash::{Hash, Hasher},
+    ops::Add,
+};
 
+use crate::{
+    microfacet::{fresnel_conductor, fresnel_dielectric, fresnel_schlick, refract, Film, Ggx},
+    medium::{Medium, Participating},
+    numbers::{float_bits, random, Color, StableHasher, Vector},
+    principled::Principled,
+    ray::{Ray, Hit, Bounce, Lobe},
+    spectrum::{sample_wavelength, wavelength_weight, Ior, Spectrum, Wavelengths},
+    texture::{Bump, Texture},
+};
+
+/// how light interacts with a surface
 pub enum Material {
-    Metal(f32, Color), // roughness and albedo
+    Metal(f32, Color), // roughness and reflectance head on
+    /// a rough metal described by its complex index of refraction eta + ik,
+    /// optionally under a thin film
+    Conductor { roughness: Ggx, eta: Color, k: Color, film: Option<Film> },
+    /// oren-nayar rough diffuse, roughness being the spread of the facet angles in
+    /// radians, where 0 is lambertian
     Diffuse(f32, Color),// roughness and albedo
-    Dielectric(f32, Color), // ior and attenuation?
+    /// glass whose index may change with wavelength, splitting white light
+    /// into colors. paths through dispersive glass narrow to one wavelength
+    Dielectric(Ior, Color), // ior and attenuation?
+    /// frosted glass, or with the tint black a rough clear coating that only reflects,
+    /// optionally under a thin film
+    RoughDielectric { roughness: Ggx, ior: f32, tint: Color, film: Option<Film> },
+    Principled(Box<Principled>),
+    /// the second material where the weight is 1, the first where it's 0, and a blend between
+    Mix { a: Box<Material>, b: Box<Material>, weight: Texture },
+    /// a clear dielectric layer over any other material, like varnish or the
+    /// clearcoat on car paint. light crossing the layer is tinted once each way
+    Coated { base: Box<Material>, ior: f32, roughness: Ggx, tint: Color },
+    /// a dielectric surface over a medium that light wanders through before
+    /// coming back out, like skin, wax or marble. the object must be closed
+    Subsurface { roughness: Ggx, ior: f32, interior: Medium },
+    /// another material with its shading normal bent by a normal or bump map
+    Bumped { base: Box<Material>, bump: Bump },
+    /// another material with holes cut out of it where the opacity is low,
+    /// which rays pass straight through, like leaves drawn on flat cards
+    Masked { base: Box<Material>, opacity: Texture, mask: Mask },
+    /// a surface giving off light from its front and scattering none, like a
+    /// lamp's shade. to light anything it has to be a light as well, see
+    /// `World::with_emitter`
+    Emissive(Spectrum),
+}
+
+/// by everything that decides how a material looks, so materials built the same
+/// way hash alike from run to run, wherever they happen to sit in memory
+impl Hash for Material {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let floats = |state: &mut H, values: &[f32]| values.iter().for_each(|v| float_bits(*v).hash(state));
+        let ggx = |state: &mut H, ggx: &Ggx| floats(state, &[ggx.alpha_x, ggx.alpha_y]);
+        let film = |state: &mut H, film: &Option<Film>| {
+            film.is_some().hash(state);
+            if let Some(film) = film {
+                floats(state, &[film.thickness, film.ior]);
+            }
+        };
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Material::Metal(roughness, color) | Material::Diffuse(roughness, color) => {
+                floats(state, &[*roughness]);
+                color.hash(state);
+            }
+            Material::Conductor { roughness, eta, k, film: thin } => {
+                ggx(state, roughness);
+                eta.hash(state);
+                k.hash(state);
+                film(state, thin);
+            }
+            Material::Dielectric(ior, color) => {
+                match ior {
+                    Ior::Constant(n) => floats(state, &[*n]),
+                    Ior::Cauchy { a, b } => floats(state, &[*a, *b]),
+                    Ior::Sellmeier { b, c } => floats(state, &[b.as_slice(), c.as_slice()].concat()),
+                }
+                color.hash(state);
+            }
+            Material::RoughDielectric { roughness, ior, tint, film: thin } => {
+                ggx(state, roughness);
+                floats(state, &[*ior]);
+                tint.hash(state);
+                film(state, thin);
+            }
+            Material::Principled(principled) => principled.hash(state),
+            Material::Mix { a, b, weight } => {
+                a.hash(state);
+                b.hash(state);
+                weight.hash(state);
+            }
+            Material::Coated { base, ior, roughness, tint } => {
+                base.hash(state);
+                floats(state, &[*ior]);
+                ggx(state, roughness);
+                tint.hash(state);
+            }
+            Material::Subsurface { roughness, ior, interior } => {
+                ggx(state, roughness);
+                floats(state, &[*ior, interior.g]);
+                interior.absorption.hash(state);
+                interior.scattering.hash(state);
+            }
+            Material::Bumped { base, bump } => {
+                base.hash(state);
+                bump.hash(state);
+            }
+            Material::Masked { base, opacity, mask } => {
+                base.hash(state);
+                opacity.hash(state);
+                match mask {
+                    Mask::Threshold(threshold) => floats(state, &[*threshold]),
+                    Mask::Stochastic => floats(state, &[-1.0]),
+                }
+            }
+            Material::Emissive(radiance) => radiance.rgb().hash(state),
+        }
+    }
+}
+
+/// how opacity decides whether a ray goes through a masked material
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Mask {
+    /// solid where the opacity is at least this, and a hole elsewhere
+    Threshold(f32),
+    /// solid for a fraction of the rays given by the opacity, for soft edges
+    Stochastic,
+}
+
+/// most times light goes back and forth inside a coating before it's let go
+const COATING_BOUNCES: usize = 8;
+
+impl Default for Material {
+    fn default() -> Material {
+        Material::new()
+    }
 }
 
 impl Material {
@@ -12,62 +145,571 @@ impl Material {
     pub const TEST_METAL_RED: Material = Material::Metal(0.25, Color::REDDISH); 
     pub const TEST_METAL_BLUE: Material = Material::Metal(0.0, Color::BLUE);
     pub const TEST_ROUGH: Material = Material::Diffuse(0.5, Color::BLUE);
-    pub const TEST_DIE: Material = Material::Dielectric(1.5, Color::BLUE);
+    pub const TEST_DIE: Material = Material::Dielectric(Ior::Constant(1.5), Color::BLUE);
+    /// a dull gray diffuse surface
     pub const fn new() -> Material {
-        Material::Diffuse(1.0, Color::GRAY)
+        Material::Diffuse(0.0, Color::GRAY)
+    }
+    pub fn conductor(eta: Color, k: Color, roughness: Ggx) -> Material {
+        Material::Conductor { roughness, eta, k, film: None }
+    }
+    pub fn principled(principled: Principled) -> Material {
+        Material::Principled(Box::new(principled))
+    }
+    pub fn mix(a: Material, b: Material, weight: impl Into<Texture>) -> Material {
+        Material::Mix { a: Box::new(a), b: Box::new(b), weight: weight.into() }
+    }
+    /// a clear coating of roughness and index of refraction over a base
+    pub fn coated(base: Material, ior: f32, roughness: f32) -> Material {
+        Material::Coated { base: Box::new(base), ior, roughness: Ggx::isotropic(roughness), tint: Color::WHITE }
+    }
+    /// light goes on average `mean_free_path` into the object before it scatters,
+    /// and `albedo` of it scatters rather than being absorbed, per channel. the
+    /// surface is glass of this index and roughness
+    pub fn subsurface(albedo: Color, mean_free_path: Color, ior: f32, roughness: f32) -> Material {
+        let extinction = mean_free_path.map(|d| d.recip());
+        let interior = Medium::new(extinction.clone() * albedo.map(|a| 1.0 - a), extinction * albedo, 0.0);
+        Material::Subsurface { roughness: Ggx::isotropic(roughness), ior, interior }
+    }
+    /// light given off back along the hit's ray, in the path's channels
+    pub fn emission(&self, hit: &Hit) -> Option<Color> {
+        match self {
+            Material::Emissive(radiance) if hit.front => Some(radiance.sample(&hit.by.wavelengths)),
+            _ => None,
+        }
+    }
+    /// a number for telling materials apart, the same for materials built the
+    /// same way in every run and on every machine
+    pub fn id(&self) -> u64 {
+        let mut hasher = StableHasher::default();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+    /// the medium filling the object, for light that went in through this surface
+    pub fn interior(&self) -> Option<&Medium> {
+        match self {
+            Material::Subsurface { interior, .. } => Some(interior),
+            Material::Coated { base, .. } | Material::Bumped { base, .. } | Material::Masked { base, .. } => base.interior(),
+            _ => None,
+        }
+    }
+    /// the fraction of the light along a shadow ray from inside a translucent
+    /// object that gets out through its surface at `hit`, or None if the hit
+    /// blocks it. the light isn't bent on its way out, so lights can still be
+    /// found from inside, a little off where refraction would put them
+    pub fn shadow_through(&self, hit: &Hit) -> Option<Color> {
+        match self {
+            Material::Subsurface { ior, interior, .. } if !hit.front => {
+                let cos = hit.by.direction.unit().dot(&hit.normal).abs();
+                let inside = interior.transmittance_between(&hit.by, 0.0, hit.length);
+                Some(inside * (1.0 - fresnel_dielectric(cos, 1.0 / ior)))
+            }
+            _ => None,
+        }
+    }
+    pub fn bumped(base: Material, bump: Bump) -> Material {
+        Material::Bumped { base: Box::new(base), bump }
+    }
+    pub fn masked(base: Material, opacity: impl Into<Texture>, mask: Mask) -> Material {
+        Material::Masked { base: Box::new(base), opacity: opacity.into(), mask }
+    }
+    /// whether a ray stops at this hit or goes on through a hole in the material
+    pub fn is_solid(&self, hit: &Hit) -> bool {
+        match self {
+            Material::Masked { base, opacity, mask } => {
+                let alpha = opacity.value(&hit.uv);
+                let solid = match mask {
+                    Mask::Threshold(threshold) => alpha >= *threshold,
+                    Mask::Stochastic => random() < alpha,
+                };
+                solid && base.is_solid(hit)
+            }
+            Material::Coated { base, .. } | Material::Bumped { base, .. } => base.is_solid(hit),
+            Material::Mix { a, b, weight } => {
+                let (side, hit) = pick(a, b, weight, hit);
+                side.is_solid(&hit)
+            }
+            _ => true,
+        }
+    }
+    /// whether there's a mixed material to pick a side of
+    pub fn mixes(&self) -> bool {
+        match self {
+            Material::Mix { .. } => true,
+            Material::Masked { base, .. } | Material::Coated { base, .. } | Material::Bumped { base, .. } => base.mixes(),
+            _ => false,
+        }
+    }
+    pub fn rough_dielectric(ior: f32, roughness: Ggx, tint: Color) -> Material {
+        Material::RoughDielectric { roughness, ior, tint, film: None }
+    }
+    /// clear glass splitting light by its wavelength-dependent index
+    pub fn dispersive(ior: Ior) -> Material {
+        Material::Dielectric(ior, Color::WHITE)
+    }
+    /// a soap bubble: a film of soapy water thin enough that light goes
+    /// straight through it, with air on both sides
+    pub fn bubble(thickness: f32) -> Material {
+        let film = Some(Film::new(thickness, 1.33));
+        Material::RoughDielectric { roughness: Ggx::isotropic(0.0), ior: 1.0, tint: Color::WHITE, film }
+    }
+    /// puts a thin film over a conductor or glass. smooth glass becomes the smooth
+    /// kind of rough glass, tinting only what goes through, and glass that splits
+    /// light by wavelength can't take a film, nor can anything else, giving None
+    pub fn with_film(self, film: Film) -> Option<Material> {
+        match self {
+            Material::Conductor { roughness, eta, k, .. } => Some(Material::Conductor { roughness, eta, k, film: Some(film) }),
+            Material::RoughDielectric { roughness, ior, tint, .. } => {
+                Some(Material::RoughDielectric { roughness, ior, tint, film: Some(film) })
+            }
+            Material::Dielectric(Ior::Constant(ior), tint) => {
+                Some(Material::RoughDielectric { roughness: Ggx::isotropic(0.0), ior, tint, film: Some(film) })
+            }
+            _ => None,
+        }
+    }
+    /// rough glass letting through everything it doesn't reflect
+    pub fn frosted(ior: f32, roughness: f32) -> Material {
+        Material::rough_dielectric(ior, Ggx::isotropic(roughness), Color::WHITE)
+    }
+    // indices at roughly 650, 550 and 450nm, from refractiveindex.info
+    pub fn gold(roughness: f32) -> Material {
+        Material::conductor(Color::new(0.143, 0.374, 1.442), Color::new(3.983, 2.385, 1.603), Ggx::isotropic(roughness))
+    }
+    pub fn copper(roughness: f32) -> Material {
+        Material::conductor(Color::new(0.200, 0.924, 1.102), Color::new(3.912, 2.452, 2.142), Ggx::isotropic(roughness))
+    }
+    pub fn aluminium(roughness: f32) -> Material {
+        Material::conductor(Color::new(1.657, 0.880, 0.521), Color::new(9.224, 6.270, 4.837), Ggx::isotropic(roughness))
+    }
+    pub fn silver(roughness: f32) -> Material {
+        Material::conductor(Color::new(0.155, 0.117, 0.138), Color::new(4.828, 3.122, 2.147), Ggx::isotropic(roughness))
+    }
+    pub fn iron(roughness: f32) -> Material {
+        Material::conductor(Color::new(2.911, 2.950, 2.585), Color::new(3.089, 2.932, 2.767), Ggx::isotropic(roughness))
+    }
+    pub fn chromium(roughness: f32) -> Material {
+        Material::conductor(Color::new(3.180, 3.180, 2.010), Color::new(3.300, 3.330, 3.040), Ggx::isotropic(roughness))
+    }
+}
+
+/// the side of a mix used at a hit, chosen by the hit's pick, and the hit with
+/// what's left of the pick to choose within that side
+fn pick<'a>(a: &'a Material, b: &'a Material, weight: &Texture, hit: &Hit) -> (&'a Material, Hit) {
+    let t = weight.value(&hit.uv);
+    let u = hit.pick.unwrap_or_else(random);
+    let (side, rest) = if u < t { (b, u / t) } else { (a, (u - t) / (1.0 - t)) };
+    (side, Hit { pick: Some(rest), ..hit.clone() })
+}
+
+/// how much brighter or darker a rough diffuse surface is than a lambertian one
+/// for light between the two directions, from oren and nayar's qualitative model
+fn oren_nayar(sigma: f32, wo: &Vector, wi: &Vector) -> f32 {
+    if sigma == 0.0 {
+        return 1.0;
+    }
+    let s2 = sigma * sigma;
+    let a = 1.0 - 0.5 * s2 / (s2 + 0.33);
+    let b = 0.45 * s2 / (s2 + 0.09);
+    let sin_i = (1.0 - wi.z * wi.z).max(0.0).sqrt();
+    let sin_o = (1.0 - wo.z * wo.z).max(0.0).sqrt();
+    let cos_phi = if sin_i > 1e-4 && sin_o > 1e-4 {
+        ((wi.x * wo.x + wi.y * wo.y) / (sin_i * sin_o)).max(0.0)
+    } else {
+        0.0
+    };
+    // alpha is the larger of the two angles from the normal and beta the smaller
+    let (sin_alpha, tan_beta) = if wi.z.abs() > wo.z.abs() {
+        (sin_o, sin_i / wi.z.abs().max(1e-4))
+    } else {
+        (sin_i, sin_o / wo.z.abs().max(1e-4))
+    };
+    a + b * cos_phi * sin_alpha * tan_beta
+}
+
+/// scatters light off a coated base without tracking where in the layer it goes,
+/// as if it were infinitely thin: light either reflects off the top of the coating,
+/// or goes in and bounces between the base and the underside of the coating until
+/// it gets back out. only the top is rough, the underside is smooth
+fn scatter_coated(hit: Hit, base: &Material, ior: f32, roughness: &Ggx, tint: &Color) -> Option<Bounce> {
+    let frame = hit.shading_frame();
+    let wo = frame.to_local(&-hit.by.direction.unit());
+    if wo.z <= 0.0 {
+        return None;
+    }
+    let tint = hit.by.wavelengths.reflectance(tint);
+    let up = Vector::new(0.0, 0.0, 1.0);
+    let m = if roughness.is_smooth() { up } else { roughness.sample_visible(&wo, random(), random()) };
+    let reflected = (-wo).reflect(&m);
+    let inside = refract(&wo, &m, ior);
+    let inside = match inside {
+        Some(inside) if random() >= fresnel_dielectric(wo.dot(&m), ior) => inside,
+        _ if reflected.z > 0.0 => {
+            let weight = if roughness.is_smooth() { 1.0 } else { roughness.g2(&wo, &reflected) / roughness.g1(&wo) };
+            return Some(Bounce { ray: Ray::new(hit.pos, frame.to_world(&reflected)), attenuation: Color::gray(weight), lobe: roughness.lobe() });
+        }
+        _ => return None,
+    };
+    // lengths through the layer, relative to going straight across
+    let crossing = |w: &Vector| tint.map(|t| t.powf(1.0 / w.z.abs().max(1e-3)));
+    let mut attenuation = crossing(&inside);
+    let mut down = inside;
+    for _ in 0..COATING_BOUNCES {
+        let under = Hit { by: Ray::new(hit.pos, frame.to_world(&down)), ..hit.clone() };
+        let Bounce { ray, attenuation: by_base, lobe } = base.scatter(under)?;
+        let w = frame.to_local(&ray.direction.unit());
+        if w.z <= 0.0 {
+            return None;
+        }
+        attenuation = attenuation * by_base * crossing(&w);
+        // from inside the index outside is 1 / ior of the one inside
+        match refract(&-w, &-up, 1.0 / ior) {
+            Some(out) if random() >= fresnel_dielectric(w.z, 1.0 / ior) => {
+                // what comes back out is mostly what the base did to it
+                return Some(Bounce { ray: Ray::new(hit.pos, frame.to_world(&out)), attenuation, lobe });
+            }
+            _ => {
+                down = w.reflect(&up);
+                attenuation = attenuation * crossing(&down);
+            }
+        }
     }
+    None
 }
 
+/// reflects or refracts light at the surface of glass of index `ior`, rough or smooth,
+/// tinting what goes through
+fn scatter_dielectric(hit: &Hit, roughness: &Ggx, ior: f32, tint: &Color, film: Option<&Film>) -> Option<Bounce> {
+    let Hit { by, pos, front, .. } = hit;
+    let eta = if *front { ior } else { 1.0 / ior };
+    let frame = hit.shading_frame();
+    let wo = frame.to_local(&-by.direction.unit());
+    if let Some(film) = film {
+        let outside = if *front { 1.0 } else { ior };
+        let (wi, attenuation) = scatter_film(hit, film, roughness, outside, outside * eta, tint, &wo)?;
+        return Some(Bounce { ray: Ray::new(*pos, frame.to_world(&wi)), attenuation, lobe: roughness.lobe() });
+    }
+    let (wi, weight) = if roughness.is_smooth() {
+        // a single flat facet: reflect or refract by the fresnel term
+        let flat = Vector::new(0.0, 0.0, 1.0);
+        match refract(&wo, &flat, eta) {
+            Some(bent) if random() >= fresnel_dielectric(wo.z, eta) => (bent, 1.0),
+            _ => ((-wo).reflect(&flat), 1.0),
+        }
+    } else {
+        let sampled = roughness.sample_dielectric(&wo, eta, random(), random(), random())?;
+        (sampled.wi, sampled.weight)
+    };
+    // only light refracted through the surface picks up the tint
+    let attenuation = if wi.z < 0.0 { tint.clone() * weight } else { Color::gray(weight) };
+    Some(Bounce { ray: Ray::new(*pos, frame.to_world(&wi)), attenuation, lobe: roughness.lobe() })
+}
+
+/// reflects or lets light through a dielectric under a thin film, returning the
+/// local direction and attenuation. `outside` is the index on the side the light
+/// comes from and `inside` the index past the surface. the film is thin enough
+/// that light leaves it at the angle it would without it
+fn scatter_film(hit: &Hit, film: &Film, roughness: &Ggx, outside: f32, inside: f32, tint: &Color, wo: &Vector) -> Option<(Vector, Color)> {
+    if wo.z <= 0.0 {
+        return None;
+    }
+    let m = if roughness.is_smooth() { Vector::new(0.0, 0.0, 1.0) } else { roughness.sample_visible(wo, random(), random()) };
+    let reflectance = match refract(wo, &m, inside / outside) {
+        Some(_) => film.reflectance(wo.dot(&m), outside, &Color::gray(inside), &Color::BLACK, &hit.by.wavelengths),
+        None => Color::WHITE,
+    };
+    // choose by the average reflectance, weighting each channel for it
+    let chance = reflectance.average();
+    let (wi, weight) = if random() < chance {
+        ((-*wo).reflect(&m), reflectance * chance.recip())
+    } else {
+        let bent = refract(wo, &m, inside / outside)?;
+        (bent, reflectance.map(|r| 1.0 - r) * (1.0 - chance).recip() * hit.by.wavelengths.reflectance(tint))
+    };
+    if roughness.is_smooth() {
+        return Some((wi, weight));
+    }
+    // the facet may send light to the wrong side of the surface
+    if (wi.dot(&m) > 0.0) != (wi.z > 0.0) {
+        return None;
+    }
+    Some((wi, weight * (roughness.g2(wo, &wi) / roughness.g1(wo))))
+}
+
+/// the light a microfacet surface reflects toward wo from wi, cosine included,
+/// in local directions. `fresnel` gives the reflectance as for `reflect_microfacet`
+fn eval_microfacet(roughness: &Ggx, wo: &Vector, wi: &Vector, fresnel: impl Fn(f32) -> Color) -> Color {
+    if roughness.is_smooth() || wo.z <= 0.0 || wi.z <= 0.0 {
+        return Color::BLACK;
+    }
+    let m = (*wo + *wi).unit();
+    fresnel(wo.dot(&m)) * (roughness.d(&m) * roughness.g2(wo, wi) / (4.0 * wo.z))
+}
+
+/// reflects light off a microfacet surface, choosing among the facets the
+/// incoming light can see. `fresnel` gives the reflectance for the cosine
+/// between the light and the chosen facet
+fn reflect_microfacet(hit: &Hit, roughness: &Ggx, fresnel: impl Fn(f32) -> Color) -> Option<Bounce> {
+    let Hit { by, pos, normal, .. } = hit;
+    let direction = by.direction.unit();
+    if roughness.is_smooth() {
+        let attenuation = fresnel((-direction).dot(normal));
+        return Some(Bounce { ray: Ray::new(*pos, direction.reflect(normal)), attenuation, lobe: Lobe::Specular });
+    }
+    // anisotropic roughness runs along the tangent
+    let frame = hit.shading_frame();
+    let wo = frame.to_local(&-direction);
+    if wo.z <= 0.0 {
+        return None;
+    }
+    let m = roughness.sample_visible(&wo, random(), random());
+    let wi = (-wo).reflect(&m);
+    if wi.z <= 0.0 {
+        return None;
+    }
+    // the sampled facets carry the distribution and the masking from wo,
+    // leaving the fresnel term and the shadowing toward wi
+    let shadowing = roughness.g2(&wo, &wi) / roughness.g1(&wo);
+    let attenuation = fresnel(wo.dot(&m)) * shadowing;
+    Some(Bounce { ray: Ray::new(*pos, frame.to_world(&wi)), attenuation, lobe: Lobe::Glossy })
+}
+
+/// decides where light goes after hitting a surface
 pub trait Shader {
     fn scatter(&self, ray: Hit) -> Option<Bounce>;
+    /// the light sent back along the hit's ray for each unit of light arriving
+    /// from the unit direction `wi`, cosine included. perfectly smooth surfaces
+    /// only pass on light that scattering finds, so they give nothing here
+    fn eval(&self, hit: &Hit, wi: &Vector) -> Color {
+        self.eval_by_lobe(hit, wi).total()
+    }
+    /// `eval`, split by how the surface scatters the light
+    fn eval_by_lobe(&self, _hit: &Hit, _wi: &Vector) -> ByLobe {
+        ByLobe::NONE
+    }
+}
+
+/// light sent on by a surface, split into what it spreads about and what it
+/// sends on glossily. only smooth surfaces scatter specularly, which `eval` leaves out
+#[derive(Clone, Debug, PartialEq)]
+pub struct ByLobe {
+    pub diffuse: Color,
+    pub glossy: Color,
+}
+
+impl ByLobe {
+    pub const NONE: ByLobe = ByLobe { diffuse: Color::BLACK, glossy: Color::BLACK };
+    pub fn diffuse(diffuse: Color) -> ByLobe {
+        ByLobe { diffuse, ..ByLobe::NONE }
+    }
+    pub fn glossy(glossy: Color) -> ByLobe {
+        ByLobe { glossy, ..ByLobe::NONE }
+    }
+    pub fn get(&self, lobe: Lobe) -> &Color {
+        match lobe {
+            Lobe::Diffuse => &self.diffuse,
+            Lobe::Glossy => &self.glossy,
+            Lobe::Specular => &Color::BLACK,
+        }
+    }
+    pub fn total(&self) -> Color {
+        self.diffuse.clone() + self.glossy.clone()
+    }
+    /// applies a function to both parts
+    pub fn map(&self, f: impl Fn(Color) -> Color) -> ByLobe {
+        ByLobe { diffuse: f(self.diffuse.clone()), glossy: f(self.glossy.clone()) }
+    }
+}
+
+impl Add for ByLobe {
+    type Output = ByLobe;
+    fn add(self, rhs: ByLobe) -> ByLobe {
+        ByLobe { diffuse: self.diffuse + rhs.diffuse, glossy: self.glossy + rhs.glossy }
+    }
 }
 
 impl Shader for Material {
+    /// materials work in rgb, and what they give back is carried over to the
+    /// path's wavelengths here, except by those that handle wavelengths themselves
     fn scatter(&self, hit: Hit) -> Option<Bounce> {
+        let wavelengths = hit.by.wavelengths;
+        let bounce = self.scatter_rgb(hit)?;
+        if self.handles_wavelengths() {
+            return Some(bounce);
+        }
+        Some(Bounce { attenuation: wavelengths.reflectance(&bounce.attenuation), ..bounce })
+    }
+    fn eval_by_lobe(&self, hit: &Hit, wi: &Vector) -> ByLobe {
+        // as when scattering, light can't come in through the true surface
+        // on the side a bent normal puts it
+        if (wi.dot(&hit.geometric) > 0.0) != (wi.dot(&hit.normal) > 0.0) {
+            return ByLobe::NONE;
+        }
+        let f = self.eval_rgb(hit, wi);
+        if self.handles_wavelengths() {
+            f
+        } else {
+            f.map(|f| hit.by.wavelengths.reflectance(&f))
+        }
+    }
+}
+
+impl Material {
+    /// whether scattering already gives attenuation in the path's channels: materials
+    /// that depend on wavelength, and those made of other materials which do it for them
+    fn handles_wavelengths(&self) -> bool {
+        use Material::*;
+        match self {
+            Mix { .. } | Masked { .. } | Bumped { .. } | Coated { .. } => true,
+            Conductor { film, .. } | RoughDielectric { film, .. } => film.is_some(),
+            Dielectric(ior, _) => ior.is_dispersive(),
+            Metal(..) | Diffuse(..) | Principled(_) | Subsurface { .. } | Emissive(_) => false,
+        }
+    }
+    fn eval_rgb(&self, hit: &Hit, wi: &Vector) -> ByLobe {
+        use Material::*;
+        let frame = hit.shading_frame();
+        let wo = frame.to_local(&-hit.by.direction.unit());
+        let wi_local = frame.to_local(wi);
+        let eta = |ior: f32| if hit.front { ior } else { 1.0 / ior };
+        match self {
+            Metal(roughness, color) => ByLobe::glossy(eval_microfacet(&Ggx::isotropic(*roughness), &wo, &wi_local, |cos| {
+                fresnel_schlick(cos, color)
+            })),
+            Conductor { roughness, eta, k, film } => ByLobe::glossy(eval_microfacet(roughness, &wo, &wi_local, |cos| match film {
+                Some(film) => film.reflectance(cos, 1.0, eta, k, &hit.by.wavelengths),
+                None => fresnel_conductor(cos, eta, k),
+            })),
+            Diffuse(roughness, color) if wo.z > 0.0 && wi_local.z > 0.0 => {
+                ByLobe::diffuse(color.clone() * (oren_nayar(*roughness, &wo, &wi_local) * wi_local.z / PI))
+            }
+            // films are only lit by light that scattering finds
+            RoughDielectric { roughness, ior, tint, film: None } if !roughness.is_smooth() => {
+                let f = roughness.eval_dielectric(&wo, &wi_local, eta(*ior)) * wi_local.z.abs();
+                ByLobe::glossy(if wi_local.z < 0.0 { tint.clone() * f } else { Color::gray(f) })
+            }
+            // light reaching the inside is left to the walk
+            Subsurface { roughness, ior, .. } if !roughness.is_smooth() && wi_local.z > 0.0 => {
+                ByLobe::glossy(Color::gray(roughness.eval_dielectric(&wo, &wi_local, eta(*ior)) * wi_local.z))
+            }
+            Principled(principled) => principled.eval_by_lobe(hit, wi),
+            Mix { a, b, weight } => {
+                let (side, hit) = pick(a, b, weight, hit);
+                side.eval_by_lobe(&hit, wi)
+            }
+            Masked { base, .. } => base.eval_by_lobe(hit, wi),
+            Bumped { base, bump } => base.eval_by_lobe(&Hit { normal: bump.normal(hit), ..hit.clone() }, wi),
+            Coated { base, .. } if !hit.front => base.eval_by_lobe(hit, wi),
+            Coated { base, ior, roughness, tint } => {
+                let top = eval_microfacet(roughness, &wo, &wi_local, |cos| Color::gray(fresnel_dielectric(cos, *ior)));
+                // the base lit through the coating, treating the coating as smooth on the way
+                // and ignoring light bouncing back and forth inside it
+                let through = (1.0 - fresnel_dielectric(wo.z, *ior)) * (1.0 - fresnel_dielectric(wi_local.z, *ior));
+                let inside = |cos: f32| (1.0 - (1.0 - cos * cos) / (ior * ior)).max(1e-3).sqrt();
+                let lengths = 1.0 / inside(wo.z) + 1.0 / inside(wi_local.z);
+                let tint = hit.by.wavelengths.reflectance(tint).map(|t| t.powf(lengths));
+                ByLobe::glossy(top) + base.eval_by_lobe(hit, wi).map(|f| f * tint.clone() * through)
+            }
+            _ => ByLobe::NONE,
+        }
+    }
+    fn scatter_rgb(&self, hit: Hit) -> Option<Bounce> {
         let Hit{by, pos, normal, front, ..} = hit;
         use Material::*;
         match self {
             Metal(roughness, color) => {
-                let reflected = by.direction.unit().reflect(&normal);
-                let rough_reflected = if *roughness != 0.0 {
-                    let random = Vector::random() * *roughness;
-                    random + reflected
-                } else {
-                    reflected
-                };
-                let ray = Ray::new(pos,rough_reflected);
-                let attenuation = color.clone();
-                if ray.direction.dot(&normal) > 0.0 {
-                    Some(Bounce {ray, attenuation})
+                reflect_microfacet(&hit, &Ggx::isotropic(*roughness), |cos| fresnel_schlick(cos, color))
+            },
+            Conductor { roughness, eta, k, film: None } => {
+                reflect_microfacet(&hit, roughness, |cos| fresnel_conductor(cos, eta, k))
+            },
+            Conductor { roughness, eta, k, film: Some(film) } => {
+                reflect_microfacet(&hit, roughness, |cos| film.reflectance(cos, 1.0, eta, k, &by.wavelengths))
+            },
+            RoughDielectric { roughness, ior, tint, film } => scatter_dielectric(&hit, roughness, *ior, tint, film.as_ref()),
+            // the walk inside happens along the path, see `interior`
+            Subsurface { roughness, ior, .. } => scatter_dielectric(&hit, roughness, *ior, &Color::WHITE, None),
+            Principled(principled) => principled.scatter(hit),
+            Emissive(_) => None,
+            Mix { a, b, weight } => {
+                let (side, hit) = pick(a, b, weight, &hit);
+                side.scatter(hit)
+            },
+            Masked { base, .. } => base.scatter(hit),
+            Bumped { base, bump } => {
+                let normal = bump.normal(&hit);
+                base.scatter(Hit { normal, ..hit })
+            },
+            Coated { base, ior, roughness, tint } => {
+                if front {
+                    scatter_coated(hit, base, *ior, roughness, tint)
                 } else {
-                    None
+                    base.scatter(hit)
                 }
             },
             Diffuse(roughness, color) => {
-                let mut scatter_direction = normal + Vector::random().unit() * *roughness;
-                if scatter_direction.near_zero() {
-                    scatter_direction = normal;
-                }
-                let ray = Ray::new(pos, scatter_direction - pos);
-                let attenuation = color.clone();
-                Some(Bounce {ray, attenuation})
+                let frame = hit.shading_frame();
+                let wo = frame.to_local(&-by.direction.unit());
+                // cosine sampling cancels the lambertian term, leaving the albedo
+                let wi = Vector::random_cosine();
+                let attenuation = color.clone() * oren_nayar(*roughness, &wo, &wi);
+                Some(Bounce { ray: Ray::new(pos, frame.to_world(&wi)), attenuation, lobe: Lobe::Diffuse })
             },
             Dielectric(ior, color) => {
-                let attenuation = color.clone();
+                // dispersive glass bends each wavelength its own way, so a path
+                // carrying several picks one here and keeps it
+                let (wavelengths, attenuation) = match by.wavelengths {
+                    Wavelengths::Rgb if ior.is_dispersive() => {
+                        let wavelength = sample_wavelength();
+                        (Wavelengths::Narrowed(wavelength), color.clone() * wavelength_weight(wavelength))
+                    }
+                    Wavelengths::Hero(w) if ior.is_dispersive() && w[1] != w[0] => {
+                        // the hero goes on alone, standing in for all three
+                        let alone = by.wavelengths.reflectance(color) * Color::new(3.0, 0.0, 0.0);
+                        (Wavelengths::Hero([w[0]; 3]), alone)
+                    }
+                    wavelengths if ior.is_dispersive() => (wavelengths, wavelengths.reflectance(color)),
+                    wavelengths => (wavelengths, color.clone()),
+                };
+                let ior = ior.at(wavelengths.hero().unwrap_or(550.0));
                 let refraction_ratio = if front {
                     1.0/ior
                 } else {
-                    *ior
+                    ior
                 };
                 let direction = by.direction.unit();
-                let refracted = direction.refract(&normal, refraction_ratio);
-                let ray = Ray::new(pos, refracted);
+                let cos_theta = (-direction).dot(&normal).min(1.0);
+                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                // past the critical angle nothing gets through, it all reflects
+                let bent = if refraction_ratio * sin_theta > 1.0 {
+                    direction.reflect(&normal)
+                } else {
+                    direction.refract(&normal, refraction_ratio)
+                };
+                let ray = Ray::new(pos, bent).with_wavelengths(wavelengths);
 
-                Some(Bounce{ ray, attenuation})
+                Some(Bounce{ ray, attenuation, lobe: Lobe::Specular })
                 
             },
         }
         
 
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_glass_reflects_past_the_critical_angle() {
+    use std::sync::Arc;
+    let glass = Arc::new(Material::Dielectric(Ior::Constant(1.5), Color::WHITE));
+    // light inside the glass meeting its top at every angle, either side of the
+    // critical angle of about 41.8 degrees
+    for tenth in 0..900 {
+        let angle = (tenth as f32 / 10.0).to_radians();
+        let ray = Ray::new(Vector::new(0.0, 0.0, 0.0), Vector::new(angle.sin(), angle.cos(), 0.0));
+        let hit = Hit::new(&ray, 1.0, ray.at(1.0), Vector::new(0.0, 1.0, 0.0), &glass);
+        let bent = glass.scatter(hit).unwrap().ray.direction;
+        assert!(bent.x.is_finite() && bent.y.is_finite());
+        // past it all the light turns back down into the glass
+        assert_eq!(bent.y < 0.0, 1.5 * angle.sin() > 1.0, "at {} degrees", tenth as f32 / 10.0);
+    }
+}
@@ -0,0 +1,284 @@
+use std::{f32::consts::PI, sync::Arc};
+
+use crate::{
+    numbers::{random, Color, Frame, Vector},
+    ray::Ray,
+    world::Solid,
+};
+
+/// a uniform medium like fog or smoke, which absorbs and scatters light
+/// at a fixed rate over each unit of distance travelled through it
+#[derive(Clone, Debug)]
+pub struct Medium {
+    /// how much light is absorbed per unit of distance, per channel
+    pub absorption: Color,
+    /// how much light is scattered per unit of distance, per channel
+    pub scattering: Color,
+    /// henyey-greenstein asymmetry, from -1 scattering straight back
+    /// through 0 scattering evenly to 1 scattering straight ahead
+    pub g: f32,
+}
+
+/// where light crossing a medium interacts with it
+#[derive(Clone, Debug)]
+pub struct Collision {
+    /// how far along the ray
+    pub length: f32,
+    /// weight for the light scattered onward from here, in the ray's channels
+    pub weight: Color,
+    /// light given off at the collision, already weighted, in the ray's channels
+    pub emission: Color,
+    /// asymmetry of the phase function the light scatters by
+    pub g: f32,
+}
+
+/// what happened to light crossing some media
+pub enum Event {
+    /// collided with a medium, to be scattered onward
+    Scatter(Collision),
+    /// made it all the way through, with this weight
+    Pass { weight: Color },
+}
+
+/// anything light can be scattered or absorbed by on its way along a ray
+pub trait Participating: Send + Sync {
+    /// samples where light travelling the ray from `start` to `end` first collides.
+    /// weights and transmittance are in the ray's channels, at its wavelengths
+    fn sample(&self, ray: &Ray, start: f32, end: f32) -> Event;
+    /// the weight `sample` gives light that made it from `start` to `end`
+    fn pass_weight(&self, ray: &Ray, start: f32, end: f32) -> Color;
+    /// estimates the fraction of light making it from `start` to `end`
+    fn transmittance_between(&self, ray: &Ray, start: f32, end: f32) -> Color;
+}
+
+impl Medium {
+    pub fn new(absorption: Color, scattering: Color, g: f32) -> Medium {
+        Medium { absorption, scattering, g }
+    }
+    /// a medium which stops `density` of the light per unit of distance,
+    /// scattering `albedo` of what it stops and absorbing the rest
+    pub fn fog(density: f32, albedo: Color, g: f32) -> Medium {
+        let absorption = Color::gray(density) * albedo.map(|a| 1.0 - a);
+        let scattering = albedo * density;
+        Medium { absorption, scattering, g }
+    }
+    /// how much light is stopped per unit of distance, by absorbing or scattering it
+    pub fn extinction(&self) -> Color {
+        self.absorption.clone() + self.scattering.clone()
+    }
+    /// the fraction of light making it through this much distance
+    pub fn transmittance(&self, distance: f32) -> Color {
+        transmittance(&self.extinction(), distance)
+    }
+    /// the extinction and scattering at the ray's wavelengths. upsampling is
+    /// linear, so rates carry over to wavelengths just as albedos do
+    fn rates(&self, ray: &Ray) -> (Color, Color) {
+        let scattering = ray.wavelengths.reflectance(&self.scattering);
+        (ray.wavelengths.reflectance(&self.absorption) + scattering.clone(), scattering)
+    }
+    /// the henyey-greenstein phase function for light turning by an angle with this cosine
+    pub fn phase(&self, cos_theta: f32) -> f32 {
+        henyey_greenstein_phase(self.g, cos_theta)
+    }
+    /// a new unit direction for light travelling along `direction`, drawn
+    /// from the phase function
+    pub fn scatter_direction(&self, direction: &Vector) -> Vector {
+        henyey_greenstein(self.g, direction)
+    }
+}
+
+/// the fraction of light making it through this much distance at these rates
+fn transmittance(extinction: &Color, distance: f32) -> Color {
+    // written out so a zero rate over an infinite distance lets everything through
+    extinction.map(|s| if s == 0.0 { 1.0 } else { (-s * distance).exp() })
+}
+
+/// the henyey-greenstein phase function with asymmetry `g`, for light turning
+/// by an angle with this cosine
+pub fn henyey_greenstein_phase(g: f32, cos_theta: f32) -> f32 {
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+}
+
+/// a new unit direction for light travelling along `direction`, scattered by
+/// the henyey-greenstein phase function with asymmetry `g`
+pub fn henyey_greenstein(g: f32, direction: &Vector) -> Vector {
+    let cos_theta = if g.abs() < 1e-3 {
+        1.0 - 2.0 * random()
+    } else {
+        let k = (1.0 - g * g) / (1.0 - g + 2.0 * g * random());
+        ((1.0 + g * g - k * k) / (2.0 * g)).clamp(-1.0, 1.0)
+    };
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+    let phi = 2.0 * PI * random();
+    let frame = Frame::from_z(direction.unit());
+    frame.to_world(&Vector::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta))
+}
+
+impl Participating for Medium {
+    /// free-flight sampling in one channel chosen at random. the weights average
+    /// over the choice of channel, so media with colored extinction stay unbiased
+    fn sample(&self, ray: &Ray, start: f32, end: f32) -> Event {
+        let speed = ray.direction.length();
+        let (extinction, scattering) = self.rates(ray);
+        let channel = ((random() * 3.0) as usize).min(2);
+        let rate = extinction.channel(channel);
+        let flight = if rate > 0.0 { -(1.0 - random()).ln() / rate } else { f32::INFINITY };
+        if flight < (end - start) * speed {
+            let transmittance = transmittance(&extinction, flight);
+            let pdf = (extinction * transmittance.clone()).average();
+            Event::Scatter(Collision {
+                length: start + flight / speed,
+                weight: transmittance * scattering * pdf.recip(),
+                emission: Color::BLACK,
+                g: self.g,
+            })
+        } else {
+            Event::Pass { weight: self.pass_weight(ray, start, end) }
+        }
+    }
+    fn pass_weight(&self, ray: &Ray, start: f32, end: f32) -> Color {
+        let transmittance = self.transmittance_between(ray, start, end);
+        let pdf = transmittance.average();
+        if pdf > 0.0 {
+            transmittance * pdf.recip()
+        } else {
+            Color::BLACK
+        }
+    }
+    fn transmittance_between(&self, ray: &Ray, start: f32, end: f32) -> Color {
+        transmittance(&self.rates(ray).0, (end - start) * ray.direction.length())
+    }
+}
+
+/// a medium filling the inside of a closed shape. the shape's surface isn't
+/// drawn, so add it to the world separately to put the medium behind glass
+pub struct Volume {
+    boundary: Arc<dyn Solid>,
+    medium: Arc<dyn Participating>,
+}
+
+impl Volume {
+    pub fn new(boundary: impl Solid + 'static, medium: impl Participating + 'static) -> Volume {
+        Volume { boundary: Arc::new(boundary), medium: Arc::new(medium) }
+    }
+    pub fn medium(&self) -> &dyn Participating {
+        self.medium.as_ref()
+    }
+    /// the stretches of the ray between near and far that lie inside the volume
+    pub fn segments(&self, ray: &Ray, near: f32, far: f32) -> Vec<(f32, f32)> {
+        self.boundary
+            .spans(ray)
+            .into_iter()
+            .map(|span| (span.enter.length.max(near), span.exit.length.min(far)))
+            .filter(|(start, end)| start < end)
+            .collect()
+    }
+}
+
+/// a medium and the stretches of a ray, as start and end lengths, that it fills
+pub type Stretches<'a> = (&'a dyn Participating, Vec<(f32, f32)>);
+
+/// samples the first scatter along the ray among several media, each filling
+/// some stretches of it. every medium samples its own first collision; the nearest
+/// wins, and the others are weighted for having let the light that far
+pub fn sample_media(ray: &Ray, far: f32, media: &[Stretches]) -> Event {
+    // weight for the light getting through one medium's stretches up to some length
+    let pass = |medium: &dyn Participating, segments: &[(f32, f32)], until: f32| {
+        segments
+            .iter()
+            .filter(|(start, _)| *start < until)
+            .fold(Color::WHITE, |w, (start, end)| w * medium.pass_weight(ray, *start, end.min(until)))
+    };
+    let nearest = media
+        .iter()
+        .enumerate()
+        .filter_map(|(index, (medium, segments))| {
+            let mut weight = Color::WHITE;
+            for (start, end) in segments {
+                match medium.sample(ray, *start, *end) {
+                    Event::Scatter(collision) => return Some((index, weight, collision)),
+                    Event::Pass { weight: through } => weight = weight * through,
+                }
+            }
+            None
+        })
+        .min_by(|a, b| a.2.length.total_cmp(&b.2.length));
+    match nearest {
+        Some((index, before, collision)) => {
+            let weight = media
+                .iter()
+                .enumerate()
+                .filter(|(other, _)| *other != index)
+                .fold(before, |w, (_, (medium, segments))| w * pass(*medium, segments, collision.length));
+            Event::Scatter(Collision {
+                weight: weight.clone() * collision.weight,
+                emission: weight * collision.emission,
+                ..collision
+            })
+        }
+        None => {
+            let weight = media
+                .iter()
+                .fold(Color::WHITE, |w, (medium, segments)| w * pass(*medium, segments, far));
+            Event::Pass { weight }
+        }
+    }
+}
+
+#[test]
+fn test_absorbing_volume_transmittance() {
+    use crate::{numbers::reseed, world::Sphere};
+    reseed(1);
+    let ink = Volume::new(
+        Sphere::new(0.0, 0.0, -3.0, 1.0),
+        Medium::new(Color::new(0.2, 0.5, 1.0), Color::BLACK, 0.0),
+    );
+    let ray = Ray::new(Vector::ORIGIN, Vector::new(0.0, 0.0, -2.0));
+    let segments = ink.segments(&ray, 0.0, f32::INFINITY);
+    assert!(segments.len() == 1 && (segments[0].0 - 1.0).abs() < 1e-5 && (segments[0].1 - 2.0).abs() < 1e-5);
+    let runs = 20000;
+    let total = (0..runs).fold(Color::BLACK, |sum, _| match sample_media(&ray, f32::INFINITY, &[(ink.medium(), segments.clone())]) {
+        Event::Pass { weight } => sum + weight,
+        Event::Scatter(_) => sum,
+    });
+    let mean = total * (1.0 / runs as f32);
+    let expected = ink.medium().transmittance_between(&ray, 1.0, 2.0);
+    for c in 0..3 {
+        assert!((mean.channel(c) - expected.channel(c)).abs() < 0.02);
+    }
+}
+
+#[test]
+fn test_phase_mean_cosine() {
+    use crate::numbers::reseed;
+    reseed(2);
+    // the average cosine of henyey-greenstein scattering is g
+    for g in [-0.6, 0.0, 0.3, 0.85] {
+        let fog = Medium::fog(1.0, Color::WHITE, g);
+        let runs = 20000;
+        let mean = (0..runs).map(|_| fog.scatter_direction(&Vector::Z_NEG).dot(&Vector::Z_NEG)).sum::<f32>() / runs as f32;
+        assert!((mean - g).abs() < 0.02);
+    }
+}
+
+#[test]
+fn test_media_follow_the_path_wavelengths() {
+    use crate::{numbers::reseed, spectrum::Wavelengths};
+    reseed(4);
+    // ink that only stops red light, seen at blue, green and red wavelengths in
+    // an order unlike rgb: the blue gets through and the red doesn't
+    let ink = Medium::new(Color::new(4.0, 0.0, 0.0), Color::BLACK, 0.0);
+    let ray = Ray::new(Vector::ORIGIN, Vector::Z_NEG).with_wavelengths(Wavelengths::Hero([450.0, 550.0, 650.0]));
+    let through = ink.transmittance_between(&ray, 0.0, 1.0);
+    assert!(through.r() > 0.8 && through.b() < 0.1, "{:?}", through);
+    let runs = 20000;
+    let total = (0..runs).fold(Color::BLACK, |sum, _| match ink.sample(&ray, 0.0, 1.0) {
+        Event::Pass { weight } => sum + weight,
+        Event::Scatter(_) => sum,
+    });
+    let mean = total * (1.0 / runs as f32);
+    for c in 0..3 {
+        assert!((mean.channel(c) - through.channel(c)).abs() < 0.02);
+    }
+}
@@ -0,0 +1,151 @@
+use std::sync::Arc;
+
+use crate::{
+    bvh::Bvh,
+    material::Material,
+    numbers::{Aabb, Uv, Vector},
+    ray::{Hit, Ray},
+    world::Hittable,
+};
+
+/// a triangle mesh with its own bounding volume hierarchy.
+/// triangles wound counter-clockwise face toward the viewer
+pub struct Mesh {
+    positions: Vec<Vector>,
+    triangles: Vec<[usize; 3]>,
+    /// texture coordinates for each position, if there are any
+    uvs: Option<Vec<Uv>>,
+    material: Arc<Material>,
+    bvh: Bvh,
+}
+
+impl Mesh {
+    /// a gray diffuse mesh; every index must point into `positions`
+    pub fn new(positions: Vec<Vector>, triangles: Vec<[usize; 3]>) -> Mesh {
+        let bounds: Vec<Aabb> = triangles
+            .iter()
+            .map(|t| Aabb::new(positions[t[0]], positions[t[1]]).including(&positions[t[2]]))
+            .collect();
+        let bvh = Bvh::new(&bounds);
+        let material = Arc::new(Material::new());
+        Mesh { positions, triangles, uvs: None, material, bvh }
+    }
+    /// adds texture coordinates, one for each position. without them each
+    /// triangle runs from 0 to 1 in u along its first edge and in v along its last
+    pub fn with_uvs(mut self, uvs: Vec<Uv>) -> Self {
+        self.uvs = Some(uvs);
+        self
+    }
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = Arc::new(material);
+        self
+    }
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+    /// the corners of one triangle
+    pub fn triangle(&self, index: usize) -> [Vector; 3] {
+        let [a, b, c] = self.triangles[index];
+        [self.positions[a], self.positions[b], self.positions[c]]
+    }
+    /// intersects one triangle using the Möller–Trumbore method
+    fn hit_triangle(&self, index: usize, ray: &Ray, near: f32, far: f32) -> Option<Hit> {
+        let [a, b, c] = self.triangle(index);
+        let edge1 = b - a;
+        let edge2 = c - a;
+        let p = ray.direction.cross(&edge2);
+        let det = edge1.dot(&p);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv = det.recip();
+        let s = ray.origin - a;
+        let u = s.dot(&p) * inv;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = s.cross(&edge1);
+        let v = ray.direction.dot(&q) * inv;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let length = edge2.dot(&q) * inv;
+        if length < near || far < length {
+            return None;
+        }
+        let normal = edge1.cross(&edge2).unit();
+        let hit = Hit::new(ray, length, ray.at(length), normal, &self.material);
+        let [uv0, uv1, uv2] = match &self.uvs {
+            Some(uvs) => self.triangles[index].map(|i| uvs[i]),
+            None => [Uv::new(0.0, 0.0), Uv::new(1.0, 0.0), Uv::new(0.0, 1.0)],
+        };
+        let w = 1.0 - u - v;
+        let uv = Uv::new(w * uv0.u + u * uv1.u + v * uv2.u, w * uv0.v + u * uv1.v + v * uv2.v);
+        // the direction along the triangle in which u grows, from how the
+        // texture coordinates change along its edges
+        let (du1, dv1, du2, dv2) = (uv1.u - uv0.u, uv1.v - uv0.v, uv2.u - uv0.u, uv2.v - uv0.v);
+        let tangent = (edge1 * dv2 - edge2 * dv1) * (du1 * dv2 - du2 * dv1).recip();
+        Some(hit.with_uv(uv.u, uv.v).with_barycentric(u, v).with_tangent(if tangent.x.is_finite() { tangent } else { edge1 }))
+    }
+}
+
+impl Hittable for Mesh {
+    fn hit(&self, ray: &Ray, near: f32, far: f32) -> Option<Hit> {
+        self.bvh.hit(ray, near, far, |index, far| self.hit_triangle(index, ray, near, far))
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.bvh.bounds())
+    }
+}
+
+#[test]
+fn test_mesh_hit() {
+    // a unit square in the z = -2 plane, facing +z
+    let square = Mesh::new(
+        vec![
+            Vector::new(-1.0, -1.0, -2.0),
+            Vector::new(1.0, -1.0, -2.0),
+            Vector::new(1.0, 1.0, -2.0),
+            Vector::new(-1.0, 1.0, -2.0),
+        ],
+        vec![[0, 1, 2], [0, 2, 3]],
+    )
+    .with_uvs(vec![Uv::new(0.0, 0.0), Uv::new(1.0, 0.0), Uv::new(1.0, 1.0), Uv::new(0.0, 1.0)]);
+    let ray = Ray::new(Vector::ORIGIN, Vector::new(-0.2, 0.3, -1.0));
+    let hit = square.hit(&ray, 0.0, f32::INFINITY).unwrap();
+    assert!((hit.length - 2.0).abs() < 1e-5);
+    assert!(hit.front);
+    assert!((hit.normal - Vector::Z_POS).length() < 1e-5);
+    // the texture runs along x and y, whichever way the triangle's edges go
+    assert!((hit.tangent - Vector::new(1.0, 0.0, 0.0)).length() < 1e-5);
+    assert!((hit.uv.u - 0.3).abs() < 1e-5 && (hit.uv.v - 0.8).abs() < 1e-5);
+    let miss = Ray::new(Vector::ORIGIN, Vector::new(2.0, 0.0, -1.0));
+    assert!(square.hit(&miss, 0.0, f32::INFINITY).is_none());
+}
+
+#[test]
+fn test_mesh_bvh_matches_brute_force() {
+    // a bumpy 8x8 grid, enough triangles for the tree to split several times
+    let size = 8;
+    let positions = (0..=size)
+        .flat_map(|j| (0..=size).map(move |i| Vector::new(i as f32, ((i * 7 + j * 3) % 5) as f32 * 0.2, j as f32)))
+        .collect();
+    let triangles = (0..size)
+        .flat_map(|j| (0..size).map(move |i| (i, j)))
+        .flat_map(|(i, j)| {
+            let corner = j * (size + 1) + i;
+            [[corner, corner + size + 1, corner + 1], [corner + 1, corner + size + 1, corner + size + 2]]
+        })
+        .collect();
+    let mesh = Mesh::new(positions, triangles);
+    for n in 0..50 {
+        let target = Vector::new((n % 10) as f32 * 0.83, 0.0, (n / 10) as f32 * 1.7);
+        let ray = Ray::new(Vector::new(4.0, 5.0, 4.0), target - Vector::new(4.0, 5.0, 4.0));
+        let fast = mesh.hit(&ray, 0.0, f32::INFINITY).map(|h| h.length);
+        let slow = (0..mesh.triangle_count())
+            .filter_map(|t| mesh.hit_triangle(t, &ray, 0.0, f32::INFINITY))
+            .map(|h| h.length)
+            .reduce(f32::min);
+        assert!(fast == slow);
+    }
+}
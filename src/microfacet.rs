@@ -0,0 +1,407 @@
+use std::{
+    f32::consts::PI,
+    ops::{Add, Div, Mul, Sub},
+};
+
+use crate::{
+    numbers::{Color, Vector},
+    ray::Lobe,
+    spectrum::Wavelengths,
+};
+
+/// the ggx (trowbridge-reitz) distribution of microfacet normals, for surfaces
+/// made of many tiny mirrors. directions are in a local frame with the surface
+/// normal along z, and the two roughnesses stretch it along x and y
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ggx {
+    pub alpha_x: f32,
+    pub alpha_y: f32,
+}
+
+impl Ggx {
+    /// below this a surface is drawn as a perfect mirror
+    const SMOOTH: f32 = 1e-3;
+
+    /// the same roughness in every direction. roughness is squared into the
+    /// distribution's alpha, which makes it look more even from 0 to 1
+    pub const fn isotropic(roughness: f32) -> Ggx {
+        Ggx { alpha_x: roughness * roughness, alpha_y: roughness * roughness }
+    }
+    /// brushed surfaces, rougher along one tangent than the other
+    pub const fn anisotropic(roughness_x: f32, roughness_y: f32) -> Ggx {
+        Ggx { alpha_x: roughness_x * roughness_x, alpha_y: roughness_y * roughness_y }
+    }
+    /// smooth enough to treat as a single mirror
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < Ggx::SMOOTH
+    }
+    /// the kind of scattering off facets this rough
+    pub fn lobe(&self) -> Lobe {
+        if self.is_smooth() { Lobe::Specular } else { Lobe::Glossy }
+    }
+    /// density of microfacets facing along m
+    pub fn d(&self, m: &Vector) -> f32 {
+        if m.z <= 0.0 {
+            return 0.0;
+        }
+        let (ax, ay) = (self.alpha_x, self.alpha_y);
+        let t = (m.x / ax).powi(2) + (m.y / ay).powi(2) + m.z * m.z;
+        1.0 / (PI * ax * ay * t * t)
+    }
+    /// the smith auxiliary function, how much of the surface seen from w is hidden
+    pub fn lambda(&self, w: &Vector) -> f32 {
+        if w.z == 0.0 {
+            return f32::INFINITY;
+        }
+        let t = ((self.alpha_x * w.x).powi(2) + (self.alpha_y * w.y).powi(2)) / (w.z * w.z);
+        0.5 * ((1.0 + t).sqrt() - 1.0)
+    }
+    /// the fraction of microfacets facing along m that can be seen from w
+    pub fn g1(&self, w: &Vector) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+    /// the fraction of microfacets seen from both directions, height-correlated
+    pub fn g2(&self, wo: &Vector, wi: &Vector) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+    /// samples a microfacet normal from those visible from wo, which must be
+    /// above the surface, after "sampling the ggx distribution of visible normals" (heitz 2018)
+    pub fn sample_visible(&self, wo: &Vector, u1: f32, u2: f32) -> Vector {
+        let (ax, ay) = (self.alpha_x, self.alpha_y);
+        // stretch the view so the distribution becomes a hemisphere
+        let vh = Vector::new(ax * wo.x, ay * wo.y, wo.z).unit();
+        let square = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if square > 0.0 {
+            Vector::new(-vh.y, vh.x, 0.0) / square.sqrt()
+        } else {
+            Vector::new(1.0, 0.0, 0.0)
+        };
+        let t2 = vh.cross(&t1);
+        // a point on the disk, squeezed into the part of it the view can see
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = t1 * p1 + t2 * p2 + vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+        // and back to the stretched distribution
+        Vector::new(ax * nh.x, ay * nh.y, nh.z.max(0.0)).unit()
+    }
+    /// density of `sample_visible` choosing the normal m when seen from wo
+    pub fn pdf_visible(&self, wo: &Vector, m: &Vector) -> f32 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * wo.dot(m).max(0.0) * self.d(m) / wo.z
+    }
+}
+
+/// a direction chosen by sampling a bsdf, in the local frame
+#[derive(Copy, Clone, Debug)]
+pub struct Scattered {
+    pub wi: Vector,
+    /// the bsdf times the cosine to the normal over the pdf
+    pub weight: f32,
+    pub pdf: f32,
+}
+
+/// rough glass, after "microfacet models for refraction through rough surfaces"
+/// (walter et al. 2007). wo is on the side the light leaves toward, which the
+/// normal faces, and eta is the index on the far side over the index on this side
+impl Ggx {
+    /// chooses between reflecting and refracting off a visible facet by its fresnel term
+    pub fn sample_dielectric(&self, wo: &Vector, eta: f32, u: f32, u1: f32, u2: f32) -> Option<Scattered> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let m = self.sample_visible(wo, u1, u2);
+        let cos_o = wo.dot(&m);
+        let reflectance = fresnel_dielectric(cos_o, eta);
+        let wi = if u < reflectance {
+            (-*wo).reflect(&m)
+        } else {
+            refract(wo, &m, eta)?
+        };
+        let pdf = self.pdf_dielectric(wo, &wi, eta);
+        // the facet's fresnel term cancels with the choice, and its distribution
+        // and masking with the sampling, leaving the shadowing
+        let reflected = wi.z > 0.0;
+        if reflected != (u < reflectance) || pdf == 0.0 {
+            return None;
+        }
+        let weight = self.g2(wo, &wi) / self.g1(wo);
+        Some(Scattered { wi, weight, pdf })
+    }
+    /// the bsdf for light arriving from wi and leaving toward wo
+    pub fn eval_dielectric(&self, wo: &Vector, wi: &Vector, eta: f32) -> f32 {
+        let Some(m) = half_vector(wo, wi, eta) else { return 0.0 };
+        let (cos_o, cos_i) = (wo.dot(&m), wi.dot(&m));
+        let reflectance = fresnel_dielectric(cos_o, eta);
+        let shared = self.d(&m) * self.g2(wo, wi) / (wo.z * wi.z).abs();
+        if wi.z > 0.0 {
+            shared * reflectance / 4.0
+        } else {
+            let denominator = (cos_i + cos_o / eta).powi(2);
+            shared * (1.0 - reflectance) * (cos_i * cos_o).abs() / denominator
+        }
+    }
+    /// the density of `sample_dielectric` choosing wi
+    pub fn pdf_dielectric(&self, wo: &Vector, wi: &Vector, eta: f32) -> f32 {
+        let Some(m) = half_vector(wo, wi, eta) else { return 0.0 };
+        let (cos_o, cos_i) = (wo.dot(&m), wi.dot(&m));
+        let reflectance = fresnel_dielectric(cos_o, eta);
+        let facet = self.pdf_visible(wo, &m);
+        if wi.z > 0.0 {
+            facet * reflectance / (4.0 * cos_o.abs())
+        } else {
+            facet * (1.0 - reflectance) * cos_i.abs() / (cos_i + cos_o / eta).powi(2)
+        }
+    }
+}
+
+/// the facet normal that takes wo to wi, by reflection when they're on the same
+/// side and refraction otherwise, or None if no facet facing wo could
+fn half_vector(wo: &Vector, wi: &Vector, eta: f32) -> Option<Vector> {
+    if wo.z <= 0.0 || wi.z == 0.0 {
+        return None;
+    }
+    let m = if wi.z > 0.0 { *wo + *wi } else { *wo + *wi * eta };
+    if m.near_zero() {
+        return None;
+    }
+    let m = if m.z < 0.0 { -m.unit() } else { m.unit() };
+    // both directions must be on the facet's side they appear to be on
+    (wo.dot(&m) > 0.0 && wi.dot(&m) * wi.z > 0.0).then_some(m)
+}
+
+/// bends wo through a facet with normal m into the far side, or None when it all reflects
+pub fn refract(wo: &Vector, m: &Vector, eta: f32) -> Option<Vector> {
+    let cos_o = wo.dot(m);
+    let sin2_i = (1.0 - cos_o * cos_o).max(0.0) / (eta * eta);
+    if sin2_i >= 1.0 {
+        return None;
+    }
+    let cos_i = (1.0 - sin2_i).sqrt();
+    Some(-*wo / eta + *m * (cos_o / eta - cos_i))
+}
+
+/// how much light glass reflects at an angle with this cosine to the normal,
+/// with eta the index on the far side over the index on this side
+pub fn fresnel_dielectric(cos_theta: f32, eta: f32) -> f32 {
+    let cos_o = cos_theta.clamp(0.0, 1.0);
+    let sin2_i = (1.0 - cos_o * cos_o) / (eta * eta);
+    if sin2_i >= 1.0 {
+        return 1.0;
+    }
+    let cos_i = (1.0 - sin2_i).sqrt();
+    let parallel = (eta * cos_o - cos_i) / (eta * cos_o + cos_i);
+    let perpendicular = (cos_o - eta * cos_i) / (cos_o + eta * cos_i);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+/// how much light a metal reflects at an angle with this cosine to the normal,
+/// per channel from its complex index of refraction eta + ik
+pub fn fresnel_conductor(cos_theta: f32, eta: &Color, k: &Color) -> Color {
+    let cos2 = cos_theta.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let channel = |eta: f32, k: f32| {
+        let t0 = eta * eta - k * k - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cos_theta.clamp(0.0, 1.0) * a;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rs + rp)
+    };
+    Color::new(channel(eta.r(), k.r()), channel(eta.g(), k.g()), channel(eta.b(), k.b()))
+}
+
+/// schlick's approximation of reflectance, from the reflectance head on
+pub fn fresnel_schlick(cos_theta: f32, f0: &Color) -> Color {
+    let t = (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5);
+    f0.map(|f| f + (1.0 - f) * t)
+}
+
+/// a thin transparent layer on a surface, like soap or oil, whose reflections
+/// interfere and tint it with colors that shift with the angle
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Film {
+    /// in nanometres, around the wavelengths of visible light
+    pub thickness: f32,
+    pub ior: f32,
+}
+
+impl Film {
+    pub fn new(thickness: f32, ior: f32) -> Film {
+        Film { thickness, ior }
+    }
+    /// reflectance per channel of the film over a surface of index eta + ik,
+    /// seen from a medium of index `outside`. the indices are given at
+    /// `RGB_WAVELENGTHS` and carried over to the channels' wavelengths
+    pub fn reflectance(&self, cos_theta: f32, outside: f32, eta: &Color, k: &Color, wavelengths: &Wavelengths) -> Color {
+        let (eta, k) = (wavelengths.interpolate(eta), wavelengths.interpolate(k));
+        let channel = |c: usize| {
+            fresnel_thin_film(cos_theta, outside, self, eta.channel(c), k.channel(c), wavelengths.at(c))
+        };
+        Color::new(channel(0), channel(1), channel(2))
+    }
+}
+
+/// just enough complex arithmetic for waves in absorbing media
+#[derive(Copy, Clone, Debug)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Complex {
+        Complex { re, im }
+    }
+    fn real(re: f64) -> Complex {
+        Complex { re, im: 0.0 }
+    }
+    fn norm2(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+    /// the root with a positive real part
+    fn sqrt(self) -> Complex {
+        let r = self.norm2().sqrt();
+        let re = (0.5 * (r + self.re)).max(0.0).sqrt();
+        let im = (0.5 * (r - self.re)).max(0.0).sqrt();
+        Complex::new(re, if self.im < 0.0 { -im } else { im })
+    }
+    /// e to the power of i times self
+    fn exp_i(self) -> Complex {
+        let scale = (-self.im).exp();
+        Complex::new(scale * self.re.cos(), scale * self.re.sin())
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, rhs: Complex) -> Complex {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex::new(self.re * rhs.re - self.im * rhs.im, self.re * rhs.im + self.im * rhs.re)
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+    fn div(self, rhs: Complex) -> Complex {
+        let n = rhs.norm2();
+        Complex::new((self.re * rhs.re + self.im * rhs.im) / n, (self.im * rhs.re - self.re * rhs.im) / n)
+    }
+}
+
+/// how much light of one wavelength in nanometres a film reflects, at an angle
+/// with this cosine, over a surface of index eta + ik (k is 0 for glass), seen
+/// from a medium of index `outside`. sums every echo between the film's two faces
+pub fn fresnel_thin_film(cos_theta: f32, outside: f32, film: &Film, eta: f32, k: f32, wavelength: f32) -> f32 {
+    let one = Complex::real(1.0);
+    let n1 = Complex::real(outside as f64);
+    let n2 = Complex::real(film.ior as f64);
+    let n3 = Complex::new(eta as f64, k as f64);
+    let cos1 = Complex::real(cos_theta.clamp(0.0, 1.0) as f64);
+    let sin2_outside = (outside as f64).powi(2) * (1.0 - cos1.re * cos1.re);
+    // snell's law carries the angle into each layer, complex past the critical angle
+    let cos_in = |n: Complex| (one - Complex::real(sin2_outside) / (n * n)).sqrt();
+    let (cos2, cos3) = (cos_in(n2), cos_in(n3));
+    // the phase one trip down and back up the film adds
+    let phase = Complex::real(4.0 * std::f64::consts::PI * film.thickness as f64 / wavelength as f64) * n2 * cos2;
+    let echo = phase.exp_i();
+    let airy = |r12: Complex, r23: Complex| ((r12 + r23 * echo) / (one + r12 * r23 * echo)).norm2();
+    let perpendicular = airy(
+        (n1 * cos1 - n2 * cos2) / (n1 * cos1 + n2 * cos2),
+        (n2 * cos2 - n3 * cos3) / (n2 * cos2 + n3 * cos3),
+    );
+    let parallel = airy(
+        (n2 * cos1 - n1 * cos2) / (n2 * cos1 + n1 * cos2),
+        (n3 * cos2 - n2 * cos3) / (n3 * cos2 + n2 * cos3),
+    );
+    (0.5 * (perpendicular + parallel)).clamp(0.0, 1.0) as f32
+}
+
+#[test]
+fn test_visible_normals_match_pdf() {
+    use crate::numbers::{random, reseed};
+    reseed(4);
+    // averages over normals drawn by the sampler should match the same averages
+    // weighted by its pdf over uniformly drawn normals
+    let ggx = Ggx::anisotropic(0.9, 0.7);
+    let wo = Vector::new(0.5, -0.3, 0.8).unit();
+    let runs = 50000;
+    let mut sampled = Vector::ORIGIN;
+    let mut weighted = Vector::ORIGIN;
+    for _ in 0..runs {
+        sampled = sampled + ggx.sample_visible(&wo, random(), random());
+        let z = random();
+        let phi = 2.0 * PI * random();
+        let r = (1.0 - z * z).sqrt();
+        let m = Vector::new(r * phi.cos(), r * phi.sin(), z);
+        weighted = weighted + m * (ggx.pdf_visible(&wo, &m) * 2.0 * PI);
+    }
+    assert!((sampled - weighted).length() / (runs as f32) < 0.01);
+}
+
+#[test]
+fn test_fresnel_conductor() {
+    // head on, a metal with no extinction reflects like glass of the same index
+    let head_on = fresnel_conductor(1.0, &Color::gray(1.5), &Color::BLACK);
+    assert!((head_on.r() - 0.04).abs() < 1e-4);
+    // and everything reflects at grazing angles
+    assert!(fresnel_conductor(0.0, &Color::gray(0.2), &Color::gray(3.0)).g() > 0.99);
+}
+
+#[test]
+fn test_rough_dielectric_sampling_matches_eval() {
+    use crate::numbers::{random, reseed};
+    reseed(5);
+    // every sampled weight should be the bsdf times the cosine over the pdf,
+    // going into the glass and coming back out of it
+    let ggx = Ggx::isotropic(0.5);
+    for (wo, eta) in [(Vector::new(0.3, 0.2, 0.9).unit(), 1.5), (Vector::new(-0.1, 0.4, 0.8).unit(), 1.0 / 1.5)] {
+        let (mut reflected, mut refracted) = (0, 0);
+        for _ in 0..2000 {
+            let Some(s) = ggx.sample_dielectric(&wo, eta, random(), random(), random()) else { continue };
+            let expected = ggx.eval_dielectric(&wo, &s.wi, eta) * s.wi.z.abs() / s.pdf;
+            assert!((s.weight - expected).abs() < 1e-3 * expected.max(1.0));
+            assert!((s.wi.length() - 1.0).abs() < 1e-3);
+            if s.wi.z > 0.0 { reflected += 1 } else { refracted += 1 }
+        }
+        assert!(reflected > 0 && refracted > reflected);
+    }
+}
+
+#[test]
+fn test_thin_film() {
+    // a film too thin to matter reflects like the surface under it
+    let none = Film::new(0.0, 1.33);
+    for cos in [1.0, 0.6, 0.2] {
+        let glass = fresnel_thin_film(cos, 1.0, &none, 1.5, 0.0, 550.0);
+        assert!((glass - fresnel_dielectric(cos, 1.5)).abs() < 1e-4);
+        let gold = none.reflectance(cos, 1.0, &Color::gray(0.37), &Color::gray(2.4), &Wavelengths::Rgb);
+        assert!((gold.r() - fresnel_conductor(cos, &Color::gray(0.37), &Color::gray(2.4)).r()).abs() < 1e-4);
+    }
+    // a soap film a quarter wave thick reflects its two faces in step
+    let quarter = Film::new(550.0 / (4.0 * 1.33), 1.33);
+    let r: f32 = (1.0 - 1.33) / (1.0 + 1.33);
+    let expected = 4.0 * r * r / (1.0 + r * r).powi(2);
+    assert!((fresnel_thin_film(1.0, 1.0, &quarter, 1.0, 0.0, 550.0) - expected).abs() < 1e-4);
+}
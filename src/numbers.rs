@@ -1,12 +1,84 @@
-use crate::{image::Pixel, world::World};
-use rand::Rng;
+use crate::image::Pixel;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use std::f32::consts::PI;
 use std::{
+    cell::RefCell,
+    hash::{Hash, Hasher},
     iter::Sum,
-    ops::{Add, Div, Mul, Neg, Sub},
+    ops::{Add, AddAssign, Div, Mul, Neg, Sub},
 };
 
+thread_local! {
+    /// per-thread generator behind every random number in the renderer,
+    /// reseeded per pixel per pass so renders are repeatable
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::seed_from_u64(0));
+}
+
+/// restarts this thread's random sequence from the given seed
+pub fn reseed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+/// produces a random value in the range 0-1 from this thread's generator
+pub fn random() -> f32 {
+    RNG.with(|rng| rng.borrow_mut().gen())
+}
+
+/// combines a render seed with a pass and pixel index into a
+/// well-distributed seed, using the splitmix64 finalizer
+pub fn mix_seed(seed: u64, pass: u32, pixel: usize) -> u64 {
+    let mut z = seed
+        ^ (pass as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (pixel as u64).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// fnv-1a, which unlike the standard library's hashers gives the same numbers
+/// in every run, on every machine and with every version of rust. numbers are
+/// taken little end first, so hash them one at a time rather than as slices
+pub struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> StableHasher {
+        StableHasher(0xCBF2_9CE4_8422_2325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0100_0000_01B3);
+        }
+    }
+    fn write_u16(&mut self, n: u16) {
+        self.write(&n.to_le_bytes());
+    }
+    fn write_u32(&mut self, n: u32) {
+        self.write(&n.to_le_bytes());
+    }
+    fn write_u64(&mut self, n: u64) {
+        self.write(&n.to_le_bytes());
+    }
+    fn write_usize(&mut self, n: usize) {
+        self.write_u64(n as u64);
+    }
+    fn write_isize(&mut self, n: isize) {
+        self.write_u64(n as u64);
+    }
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// the bits of a float to hash it by, the same for both zeros since they're equal
+pub fn float_bits(value: f32) -> u32 {
+    if value == 0.0 { 0 } else { value.to_bits() }
+}
+
 /// a group of RGB color samples
+#[derive(Clone, Debug, PartialEq)]
 pub struct Samples {
     r: f32,
     g: f32,
@@ -15,7 +87,7 @@ pub struct Samples {
 }
 
 impl Samples {
-    const NONE: Samples = Samples {
+    pub const NONE: Samples = Samples {
         r: 0.0,
         g: 0.0,
         b: 0.0,
@@ -29,6 +101,14 @@ impl Samples {
             count: 1,
         }
     }
+    /// rebuilds a group from raw channel sums and a sample count
+    pub fn from_sums(r: f32, g: f32, b: f32, count: u32) -> Samples {
+        Samples { r, g, b, count }
+    }
+    /// the raw channel sums and sample count of this group
+    pub fn sums(&self) -> (f32, f32, f32, u32) {
+        (self.r, self.g, self.b, self.count)
+    }
     pub fn to_color(&self) -> Color {
         let n = self.count as f32;
         let scale = n.recip();
@@ -52,6 +132,15 @@ impl Add for Samples {
     }
 }
 
+impl AddAssign for Samples {
+    fn add_assign(&mut self, rhs: Samples) {
+        self.r += rhs.r;
+        self.g += rhs.g;
+        self.b += rhs.b;
+        self.count += rhs.count;
+    }
+}
+
 impl Sum for Samples {
     fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
         iter.reduce(|a, s| a + s).unwrap_or(Samples::NONE)
@@ -59,13 +148,22 @@ impl Sum for Samples {
 }
 
 /// RGB color, in linear space with channels from 0-1
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Color {
     r: f32,
     g: f32,
     b: f32,
 }
 
+/// by the bits of the channels, so equal colors hash alike from run to run
+impl Hash for Color {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for channel in [self.r, self.g, self.b] {
+            float_bits(channel).hash(state);
+        }
+    }
+}
+
 impl Add for Color {
     type Output = Color;
     fn add(self, rhs: Color) -> Color {
@@ -166,6 +264,34 @@ impl Color {
     pub fn new(r: f32, g: f32, b: f32) -> Color {
         Color { r, g, b }
     }
+    /// a gray with every channel set to the same value
+    pub fn gray(value: f32) -> Color {
+        Color { r: value, g: value, b: value }
+    }
+    pub fn r(&self) -> f32 {
+        self.r
+    }
+    pub fn g(&self) -> f32 {
+        self.g
+    }
+    pub fn b(&self) -> f32 {
+        self.b
+    }
+    /// channel 0, 1 or 2
+    pub fn channel(&self, channel: usize) -> f32 {
+        match channel {
+            0 => self.r,
+            1 => self.g,
+            _ => self.b,
+        }
+    }
+    /// applies a function to each channel
+    pub fn map(&self, f: impl Fn(f32) -> f32) -> Color {
+        Color { r: f(self.r), g: f(self.g), b: f(self.b) }
+    }
+    pub fn average(&self) -> f32 {
+        (self.r + self.g + self.b) / 3.0
+    }
     /// converts this (linear) color to a (gamma-corrected) pixel value
     /// with 8-bit channels (0-255)
     pub fn to_pixel(&self) -> Pixel {
@@ -185,7 +311,7 @@ impl Color {
 }
 
 /// a three dimensional value
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Vector {
     pub x: f32,
     pub y: f32,
@@ -214,12 +340,11 @@ impl Vector {
     /// produces a new random vector with length 1
     /// uses the technique described here: https://mathworld.wolfram.com/SpherePointPicking.html
     pub fn random() -> Vector {
-        let mut rng = rand::thread_rng();
-        let u: f32 = rng.gen();
-        let v: f32 = rng.gen();
+        let u = random();
+        let v = random();
         let theta = u * 2.0 * PI;
         let phi = (2.0 * v - 1.0).acos();
-        let r = rng.gen::<f32>().cbrt();
+        let r = random().cbrt();
         let sin_theta = theta.sin();
         let cos_theta = theta.cos();
         let sin_phi = phi.sin();
@@ -229,6 +354,14 @@ impl Vector {
         let z = r * cos_phi;
         Vector { x, y, z }
     }
+    /// a random unit vector above the xy plane, more likely toward z in
+    /// proportion to the cosine, from a random point on the unit disk
+    pub fn random_cosine() -> Vector {
+        let r = random().sqrt();
+        let phi = 2.0 * PI * random();
+        let (x, y) = (r * phi.cos(), r * phi.sin());
+        Vector { x, y, z: (1.0 - x * x - y * y).max(0.0).sqrt() }
+    }
     pub fn non_zero(&self) -> bool {
         self.x != 0.0 && self.y != 0.0 && self.z != 0.0
     }
@@ -244,6 +377,29 @@ impl Vector {
     pub fn dot(&self, rhs: &Vector) -> f32 {
         self.x * rhs.x + self.y * rhs.y + self.z * rhs.z
     }
+    pub fn cross(&self, rhs: &Vector) -> Vector {
+        Vector {
+            x: self.y * rhs.z - self.z * rhs.y,
+            y: self.z * rhs.x - self.x * rhs.z,
+            z: self.x * rhs.y - self.y * rhs.x,
+        }
+    }
+    /// the smaller of each axis
+    pub fn min(&self, rhs: &Vector) -> Vector {
+        Vector::new(self.x.min(rhs.x), self.y.min(rhs.y), self.z.min(rhs.z))
+    }
+    /// the larger of each axis
+    pub fn max(&self, rhs: &Vector) -> Vector {
+        Vector::new(self.x.max(rhs.x), self.y.max(rhs.y), self.z.max(rhs.z))
+    }
+    /// the value along axis 0, 1 or 2
+    pub fn axis(&self, axis: usize) -> f32 {
+        match axis {
+            0 => self.x,
+            1 => self.y,
+            _ => self.z,
+        }
+    }
     pub fn as_color(&self) -> Color {
         Color::new(self.x + 1.0, self.y + 1.0, self.z + 1.0) * 0.5
     }
@@ -262,7 +418,8 @@ impl Vector {
     pub fn refract(&self, normal: &Vector, ratio: f32) -> Vector  {
         let cos_theta = (-*self).dot(normal).min(1.0);
         let r_out_perp =  ratio * (*self + cos_theta * *normal);
-        let r_out_parallel = (1.0 - r_out_perp.square_length()).sqrt() * -*normal;
+        // abs keeps rounding right at the critical angle from producing NaN
+        let r_out_parallel = (1.0 - r_out_perp.square_length()).abs().sqrt() * -*normal;
         r_out_perp + r_out_parallel
     }
 }
@@ -361,6 +518,322 @@ impl Sub for Vector {
     }
 }
 
+/// texture coordinates on a surface
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Uv {
+    pub u: f32,
+    pub v: f32,
+}
+
+impl Uv {
+    pub fn new(u: f32, v: f32) -> Uv {
+        Uv { u, v }
+    }
+}
+
+/// a set of three perpendicular unit axes
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub x: Vector,
+    pub y: Vector,
+    pub z: Vector,
+}
+
+impl Frame {
+    pub const WORLD: Frame = Frame {
+        x: Vector { x: 1.0, y: 0.0, z: 0.0 },
+        y: Vector { x: 0.0, y: 1.0, z: 0.0 },
+        z: Vector { x: 0.0, y: 0.0, z: 1.0 },
+    };
+    /// some frame whose z axis is the given unit vector, using the method from
+    /// "building an orthonormal basis, revisited" (duff et al. 2017)
+    pub fn from_z(z: Vector) -> Frame {
+        let sign = 1.0f32.copysign(z.z);
+        let a = -1.0 / (sign + z.z);
+        let b = z.x * z.y * a;
+        let x = Vector::new(1.0 + sign * z.x * z.x * a, sign * b, -sign * z.x);
+        let y = Vector::new(b, sign + z.y * z.y * a, -z.y);
+        Frame { x, y, z }
+    }
+    /// the world axes turned by a transform, which should only rotate
+    pub fn rotated(transform: &Transform) -> Frame {
+        Frame {
+            x: transform.vector(&Frame::WORLD.x).unit(),
+            y: transform.vector(&Frame::WORLD.y).unit(),
+            z: transform.vector(&Frame::WORLD.z).unit(),
+        }
+    }
+    /// expresses a world direction along these axes
+    pub fn to_local(&self, v: &Vector) -> Vector {
+        Vector::new(v.dot(&self.x), v.dot(&self.y), v.dot(&self.z))
+    }
+    /// turns a direction along these axes back into world space
+    pub fn to_world(&self, v: &Vector) -> Vector {
+        self.x * v.x + self.y * v.y + self.z * v.z
+    }
+}
+
+/// every real root of a polynomial between lo and hi, in increasing order.
+/// coefficients go from the constant term upward. the polynomial is monotonic
+/// between roots of its derivative, so each of those gaps holds at most one
+/// root, which is found by bisection
+pub fn roots_between(coeffs: &[f64], lo: f64, hi: f64) -> Vec<f64> {
+    let degree = match coeffs.iter().rposition(|&c| c != 0.0) {
+        Some(d) => d,
+        None => return Vec::new(),
+    };
+    let coeffs = &coeffs[..=degree];
+    if degree == 0 {
+        return Vec::new();
+    }
+    if degree == 1 {
+        let root = -coeffs[0] / coeffs[1];
+        return if lo <= root && root <= hi { vec![root] } else { Vec::new() };
+    }
+    let derivative: Vec<f64> = coeffs.iter().enumerate().skip(1).map(|(i, c)| c * i as f64).collect();
+    let mut points = vec![lo];
+    points.extend(roots_between(&derivative, lo, hi));
+    points.push(hi);
+    let eval = |x: f64| coeffs.iter().rev().fold(0.0, |sum, c| sum * x + c);
+    points
+        .windows(2)
+        .filter_map(|span| {
+            let (mut a, mut b) = (span[0], span[1]);
+            let (fa, fb) = (eval(a), eval(b));
+            if fa == 0.0 {
+                return Some(a);
+            }
+            if fa.signum() == fb.signum() {
+                return None;
+            }
+            for _ in 0..64 {
+                let m = 0.5 * (a + b);
+                let fm = eval(m);
+                if fm == 0.0 {
+                    return Some(m);
+                }
+                if fm.signum() == fa.signum() {
+                    a = m;
+                } else {
+                    b = m;
+                }
+            }
+            Some(0.5 * (a + b))
+        })
+        .fold(Vec::new(), |mut roots, r| {
+            // a root sitting exactly on a boundary can be found from both sides
+            if roots.last() != Some(&r) {
+                roots.push(r);
+            }
+            roots
+        })
+}
+
+/// an affine transform, stored as a 4x4 row-major matrix along with its inverse
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    matrix: [[f32; 4]; 4],
+    inverse: [[f32; 4]; 4],
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::IDENTITY
+    }
+}
+
+impl Transform {
+    const IDENTITY_MATRIX: [[f32; 4]; 4] = [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ];
+    pub const IDENTITY: Transform = Transform {
+        matrix: Transform::IDENTITY_MATRIX,
+        inverse: Transform::IDENTITY_MATRIX,
+    };
+    pub fn translate(x: f32, y: f32, z: f32) -> Transform {
+        let mut matrix = Transform::IDENTITY_MATRIX;
+        let mut inverse = Transform::IDENTITY_MATRIX;
+        matrix[0][3] = x;
+        matrix[1][3] = y;
+        matrix[2][3] = z;
+        inverse[0][3] = -x;
+        inverse[1][3] = -y;
+        inverse[2][3] = -z;
+        Transform { matrix, inverse }
+    }
+    /// scales each axis, none of which may be zero
+    pub fn scale(x: f32, y: f32, z: f32) -> Transform {
+        let mut matrix = Transform::IDENTITY_MATRIX;
+        let mut inverse = Transform::IDENTITY_MATRIX;
+        matrix[0][0] = x;
+        matrix[1][1] = y;
+        matrix[2][2] = z;
+        inverse[0][0] = x.recip();
+        inverse[1][1] = y.recip();
+        inverse[2][2] = z.recip();
+        Transform { matrix, inverse }
+    }
+    /// rotates counter-clockwise by `angle` radians around `axis`, looking down the axis toward the origin
+    pub fn rotate(axis: Vector, angle: f32) -> Transform {
+        let Vector { x, y, z } = axis.unit();
+        let (sin, cos) = angle.sin_cos();
+        let k = 1.0 - cos;
+        let mut matrix = Transform::IDENTITY_MATRIX;
+        matrix[0] = [x * x * k + cos, x * y * k - z * sin, x * z * k + y * sin, 0.0];
+        matrix[1] = [y * x * k + z * sin, y * y * k + cos, y * z * k - x * sin, 0.0];
+        matrix[2] = [z * x * k - y * sin, z * y * k + x * sin, z * z * k + cos, 0.0];
+        // rotations are orthogonal, so the inverse is the transpose
+        let inverse = transpose(&matrix);
+        Transform { matrix, inverse }
+    }
+    pub fn rotate_x(angle: f32) -> Transform {
+        Transform::rotate(Vector::new(1.0, 0.0, 0.0), angle)
+    }
+    pub fn rotate_y(angle: f32) -> Transform {
+        Transform::rotate(Vector::new(0.0, 1.0, 0.0), angle)
+    }
+    pub fn rotate_z(angle: f32) -> Transform {
+        Transform::rotate(Vector::new(0.0, 0.0, 1.0), angle)
+    }
+    /// a transform that applies this one and then `next`
+    pub fn then(&self, next: &Transform) -> Transform {
+        Transform {
+            matrix: multiply(&next.matrix, &self.matrix),
+            inverse: multiply(&self.inverse, &next.inverse),
+        }
+    }
+    /// the transform that undoes this one
+    pub fn inverse(&self) -> Transform {
+        Transform {
+            matrix: self.inverse,
+            inverse: self.matrix,
+        }
+    }
+    /// moves a position, including translation
+    pub fn point(&self, p: &Vector) -> Vector {
+        let m = &self.matrix;
+        Vector {
+            x: m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+            y: m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+            z: m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3],
+        }
+    }
+    /// moves a direction, ignoring translation
+    pub fn vector(&self, v: &Vector) -> Vector {
+        let m = &self.matrix;
+        Vector {
+            x: m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            y: m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            z: m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        }
+    }
+    /// moves a surface normal by the inverse transpose, so it stays perpendicular
+    /// to the transformed surface. the result is not normalized
+    pub fn normal(&self, n: &Vector) -> Vector {
+        let m = &self.inverse;
+        Vector {
+            x: m[0][0] * n.x + m[1][0] * n.y + m[2][0] * n.z,
+            y: m[0][1] * n.x + m[1][1] * n.y + m[2][1] * n.z,
+            z: m[0][2] * n.x + m[1][2] * n.y + m[2][2] * n.z,
+        }
+    }
+    /// a box holding every corner of the given box once transformed
+    pub fn bounds(&self, aabb: &Aabb) -> Aabb {
+        (0..8)
+            .map(|corner| {
+                let pick = |bit, axis| if corner & bit == 0 { aabb.min.axis(axis) } else { aabb.max.axis(axis) };
+                self.point(&Vector::new(pick(1, 0), pick(2, 1), pick(4, 2)))
+            })
+            .fold(Aabb::EMPTY, |bounds, p| bounds.including(&p))
+    }
+}
+
+fn multiply(a: &[[f32; 4]; 4], b: &[[f32; 4]; 4]) -> [[f32; 4]; 4] {
+    let mut out = [[0.0; 4]; 4];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, cell) in row.iter_mut().enumerate() {
+            *cell = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    out
+}
+
+fn transpose(m: &[[f32; 4]; 4]) -> [[f32; 4]; 4] {
+    let mut out = [[0.0; 4]; 4];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, cell) in row.iter_mut().enumerate() {
+            *cell = m[j][i];
+        }
+    }
+    out
+}
+
+/// an axis-aligned bounding box
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vector,
+    pub max: Vector,
+}
+
+impl Aabb {
+    /// a box containing nothing, which grows to fit whatever is added to it
+    pub const EMPTY: Aabb = Aabb {
+        min: Vector { x: f32::INFINITY, y: f32::INFINITY, z: f32::INFINITY },
+        max: Vector { x: f32::NEG_INFINITY, y: f32::NEG_INFINITY, z: f32::NEG_INFINITY },
+    };
+    pub fn new(a: Vector, b: Vector) -> Aabb {
+        Aabb { min: a.min(&b), max: a.max(&b) }
+    }
+    pub fn including(&self, p: &Vector) -> Aabb {
+        Aabb { min: self.min.min(p), max: self.max.max(p) }
+    }
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb { min: self.min.min(&other.min), max: self.max.max(&other.max) }
+    }
+    pub fn center(&self) -> Vector {
+        (self.min + self.max) * 0.5
+    }
+    /// the axis along which the box is longest
+    pub fn longest_axis(&self) -> usize {
+        let size = self.max - self.min;
+        if size.x > size.y && size.x > size.z {
+            0
+        } else if size.y > size.z {
+            1
+        } else {
+            2
+        }
+    }
+    /// the range of ray lengths spent inside the box, clipped to near and far,
+    /// using the slab method
+    pub fn clip(&self, origin: &Vector, direction: &Vector, near: f32, far: f32) -> Option<(f32, f32)> {
+        let mut near = near;
+        let mut far = far;
+        for axis in 0..3 {
+            let inv = direction.axis(axis).recip();
+            let mut t0 = (self.min.axis(axis) - origin.axis(axis)) * inv;
+            let mut t1 = (self.max.axis(axis) - origin.axis(axis)) * inv;
+            if inv < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // written so NaN (a ray lying in a slab face) leaves the range alone
+            if t0 > near {
+                near = t0;
+            }
+            if t1 < far {
+                far = t1;
+            }
+            if far < near {
+                return None;
+            }
+        }
+        Some((near, far))
+    }
+}
+
 #[test]
 fn test_vec_mul() {
     let a = Vector::new(1.0, 1.0, 1.0);
@@ -373,7 +846,7 @@ fn test_vec_mul() {
 
 #[test]
 fn test_pos_vec_sub() {
-    let a = Position::new(1.0, 1.0, 1.0);
+    let a = Vector::new(1.0, 1.0, 1.0);
     let b = Vector::new(2.0, 0.5, 1.0);
     let c = a - b;
     assert!(c.x == -1.0);
@@ -390,3 +863,59 @@ fn test_vec_vec_sub() {
     assert!(c.y == 0.5);
     assert!(c.z == 0.0);
 }
+
+#[test]
+fn test_reseed_repeats() {
+    reseed(mix_seed(7, 3, 42));
+    let a: Vec<f32> = (0..8).map(|_| random()).collect();
+    reseed(mix_seed(7, 3, 42));
+    let b: Vec<f32> = (0..8).map(|_| random()).collect();
+    assert!(a == b);
+    assert!(mix_seed(7, 3, 42) != mix_seed(7, 4, 42));
+}
+
+#[test]
+fn test_transform_inverse() {
+    let t = Transform::scale(2.0, 3.0, 0.5)
+        .then(&Transform::rotate(Vector::new(1.0, 2.0, 3.0), 0.7))
+        .then(&Transform::translate(1.0, -2.0, 5.0));
+    let p = Vector::new(0.3, -0.2, 4.0);
+    let back = t.inverse().point(&t.point(&p));
+    assert!((back - p).length() < 1e-5);
+    // normals stay perpendicular to transformed tangents
+    let tangent = Vector::new(1.0, 1.0, 0.0);
+    let normal = Vector::new(1.0, -1.0, 0.0);
+    assert!(t.vector(&tangent).dot(&t.normal(&normal)).abs() < 1e-5);
+}
+
+#[test]
+fn test_roots_between() {
+    // (x - 1)(x - 2)(x + 3)(x - 5) = x^4 - 5x^3 - 7x^2 + 41x - 30
+    let roots = roots_between(&[-30.0, 41.0, -7.0, -5.0, 1.0], -10.0, 10.0);
+    let expected = [-3.0, 1.0, 2.0, 5.0];
+    assert!(roots.len() == 4);
+    for (r, e) in roots.iter().zip(expected) {
+        assert!((r - e).abs() < 1e-9);
+    }
+    assert!(roots_between(&[-30.0, 41.0, -7.0, -5.0, 1.0], 1.5, 4.0).len() == 1);
+}
+
+#[test]
+fn test_frame_from_z() {
+    for z in [Vector::Z_POS, Vector::Z_NEG, Vector::new(0.3, -0.8, 0.1).unit()] {
+        let f = Frame::from_z(z);
+        assert!(f.x.dot(&f.y).abs() < 1e-6 && f.x.dot(&f.z).abs() < 1e-6 && f.y.dot(&f.z).abs() < 1e-6);
+        assert!((f.x.cross(&f.y) - f.z).length() < 1e-5);
+        let v = Vector::new(0.2, 0.5, -0.7);
+        assert!((f.to_world(&f.to_local(&v)) - v).length() < 1e-5);
+    }
+}
+
+#[test]
+fn test_refract_at_the_critical_angle() {
+    // just inside the critical angle for glass, where rounding leaves the
+    // square of the bent ray's normal part a hair below zero
+    let direction = Vector::new(0.6666668, 0.74535596, 0.0);
+    let refracted = direction.refract(&Vector::new(0.0, -1.0, 0.0), 1.5);
+    assert!(refracted.x.is_finite() && refracted.y.is_finite());
+}
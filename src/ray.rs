@@ -1,18 +1,29 @@
-use std::{rc::Rc, sync::Arc};
+use std::sync::Arc;
 
-use crate::{numbers::*, world::*, material::{Material, Shader}};
-use rand::Rng;
+use crate::{numbers::*, world::*, material::{ByLobe, Material, Shader}, medium::{henyey_greenstein, henyey_greenstein_phase, Collision, Event, Participating}, spectrum::Wavelengths};
 
 #[derive(Copy, Clone)]
 pub struct Ray {
     pub origin: Vector,
     pub direction: Vector,
+    /// what the channels of the light carried along the ray stand for
+    pub wavelengths: Wavelengths,
 }
 
 #[derive(Clone)]
 pub struct Bounce {
     pub ray: Ray,
     pub attenuation: Color,
+    /// the kind of scattering that sent the ray on
+    pub lobe: Lobe,
+}
+
+/// kinds of scattering, from spreading light everywhere to sending it one way
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Lobe {
+    Diffuse,
+    Glossy,
+    Specular,
 }
 
 #[derive(Clone)]
@@ -21,9 +32,24 @@ pub struct Hit {
     pub by: Ray,
     pub length: f32,
     pub pos: Vector,
+    /// the normal used for shading, which normal and bump maps may bend
     pub normal: Vector,
+    /// the true normal of the surface. both normals face back along the ray
+    pub geometric: Vector,
+    /// along the surface in the direction u increases, not necessarily
+    /// perpendicular to the shading normal
+    pub tangent: Vector,
     pub front: bool,
     pub material: Arc<Material>,
+    /// texture coordinates of the hit
+    pub uv: Uv,
+    /// how much the second and third corners count at the hit, on triangles
+    pub barycentric: Option<(f32, f32)>,
+    /// which of the world's objects was hit, in the order they were added
+    pub object: usize,
+    /// a random number picking the side of any mixed material, drawn once when
+    /// the hit is found so holes and scattering come from the same side
+    pub pick: Option<f32>,
 }
 
 impl Hit {
@@ -34,14 +60,93 @@ impl Hit {
             normal = -normal;
         }
         let material = material.clone();
-        let by = ray.clone();
-        Hit { by, length, pos, normal, front, material }
+        let by = *ray;
+        let tangent = Frame::from_z(normal).x;
+        Hit { by, length, pos, normal, geometric: normal, tangent, front, material, uv: Uv::default(), barycentric: None, object: 0, pick: None }
+    }
+    pub fn with_uv(mut self, u: f32, v: f32) -> Hit {
+        self.uv = Uv::new(u, v);
+        self
+    }
+    pub fn with_barycentric(mut self, u: f32, v: f32) -> Hit {
+        self.barycentric = Some((u, v));
+        self
+    }
+    /// sets the tangent, unless it's too short to have a direction like at a pole
+    pub fn with_tangent(mut self, tangent: Vector) -> Hit {
+        if tangent.square_length() > 1e-12 {
+            self.tangent = tangent.unit();
+        }
+        self
+    }
+    /// axes around the shading normal with x along the tangent
+    pub fn shading_frame(&self) -> Frame {
+        let n = self.normal;
+        let t = self.tangent - n * n.dot(&self.tangent);
+        if t.square_length() < 1e-12 {
+            return Frame::from_z(n);
+        }
+        let x = t.unit();
+        Frame { x, y: n.cross(&x), z: n }
+    }
+}
+
+/// something that happens to light on its way along a path to the camera
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PathEvent {
+    /// where every path starts, traced back from the camera
+    Camera,
+    /// scattering off a surface, or through it into what's behind
+    Surface { lobe: Lobe, through: bool },
+    /// scattering inside a medium
+    Volume,
+    /// one of the world's lights, reached by sampling it, or a glowing surface
+    /// the path ran into
+    Light,
+    /// the sky or background, reached by missing everything
+    Background,
+    /// light given off by a medium, like fire
+    Emission,
+}
+
+/// told about the light a path carries to the camera as the path is traced
+pub trait Record {
+    /// `light` reaches the camera after `events`, listed from the camera out
+    fn light(&mut self, events: &[PathEvent], light: Color);
+    /// the path reaches a surface after `events`
+    fn hit(&mut self, _events: &[PathEvent], _hit: &Hit) {}
+    /// the path scatters off the surface it reached after `events`, with `attenuation`
+    fn scatter(&mut self, _events: &[PathEvent], _attenuation: &Color) {}
+}
+
+/// all the light together, for the picture itself
+impl Record for Color {
+    fn light(&mut self, _events: &[PathEvent], light: Color) {
+        *self = self.clone() + light;
+    }
+}
+
+/// most times light scatters on a walk inside a translucent object before the
+/// walk is given up on, apart from the path's own bounces
+const WALK_STEPS: usize = 256;
+
+/// records light found by one last event after the ones so far
+fn record_ending(record: &mut impl Record, events: &mut Vec<PathEvent>, event: PathEvent, light: Color) {
+    if light == Color::BLACK {
+        return;
     }
+    events.push(event);
+    record.light(events, light);
+    events.pop();
 }
 
 impl Ray {
     pub fn new(origin: Vector, direction: Vector) -> Ray {
-        Ray { origin, direction }
+        Ray { origin, direction, wavelengths: Wavelengths::Rgb }
+    }
+    pub fn with_wavelengths(mut self, wavelengths: Wavelengths) -> Ray {
+        self.wavelengths = wavelengths;
+        self
     }
     pub fn at(&self, magnitude: f32) -> Vector {
         self.origin + (magnitude * self.direction)
@@ -51,6 +156,7 @@ impl Ray {
         Ray {
             origin: self.origin,
             direction: self.direction / length,
+            ..*self
         }
     }
     pub fn hit_sphere(&self, center: Vector, radius: f32, near: f32, far: f32, material: &Arc<Material>) -> Option<Hit> {
@@ -86,38 +192,126 @@ impl Ray {
             }
         }
     }
-    fn cast_inner(&self, world: &World, depth: usize) -> Color {
-        let hit = world.hit(self);
-        if let Some(hit) = hit {
-            // let Hit{pos, normal, material, ..} = hit;
-            if depth > 0 {
-                let material = hit.material.clone();
-                let bounce = material.scatter(hit);
-                if let Some(Bounce {ray, attenuation}) = bounce {
-                    attenuation * ray.cast_inner(world, depth - 1,)
-                } else {
-                    Color::BLACK
+    /// follows a path from the camera through at most `depth` bounces, telling
+    /// `record` about the light found along it as throughput times what's found
+    pub fn trace(&self, world: &World, mut depth: usize, record: &mut impl Record) {
+        let mut ray = *self;
+        let mut throughput = Color::WHITE;
+        let mut events = vec![PathEvent::Camera];
+        let mut steps = WALK_STEPS;
+        // whether the lights were sampled where the path last scattered, which
+        // already found any glowing surface the path goes on to reach
+        let mut sampled = false;
+        loop {
+            let hit = world.hit(&ray);
+            let far = hit.as_ref().map_or(f32::INFINITY, |hit| hit.length);
+            // leaving by the back of a surface with something inside, like skin or
+            // wax, the ray was walking through what's inside it
+            let interior = hit.as_ref().filter(|hit| !hit.front).and_then(|hit| hit.material.interior());
+            let weight = match world.sample_media(&ray, far, interior.map(|medium| medium as &dyn Participating)) {
+                Event::Scatter(Collision { length, weight, emission, g }) => {
+                    // a walk too long to follow ends dark, as if the light were lost inside
+                    if interior.is_some() && steps == 0 {
+                        return;
+                    }
+                    if interior.is_none() && depth == 0 {
+                        record.light(&events, throughput * Color::RED);
+                        return;
+                    }
+                    let pos = ray.at(length);
+                    let forward = ray.direction.unit();
+                    record_ending(record, &mut events, PathEvent::Emission, throughput.clone() * emission);
+                    throughput = throughput * weight;
+                    events.push(PathEvent::Volume);
+                    // the phase function stands in for a surface's diffuse lobe
+                    let phase = |wi: &Vector| ByLobe::diffuse(Color::gray(henyey_greenstein_phase(g, forward.dot(wi))));
+                    if let Some((_, direct)) = ray.direct_light(world, &pos, None, phase) {
+                        record_ending(record, &mut events, PathEvent::Light, throughput.clone() * direct.diffuse);
+                    }
+                    sampled = true;
+                    let direction = henyey_greenstein(g, &ray.direction);
+                    ray = Ray::new(pos, direction).with_wavelengths(ray.wavelengths);
+                    if interior.is_some() {
+                        steps -= 1;
+                    } else {
+                        depth -= 1;
+                    }
+                    continue;
                 }
-            } else {
-                // world.background_color(self)
-                Color::RED
+                Event::Pass { weight } => weight,
+            };
+            steps = WALK_STEPS;
+            let leaving = interior.is_some();
+            throughput = throughput * weight;
+            let Some(hit) = hit else {
+                record_ending(record, &mut events, PathEvent::Background, throughput * world.background_color(&ray));
+                return;
+            };
+            if depth == 0 {
+                record.light(&events, throughput * Color::RED);
+                return;
             }
-        } else {
-            world.background_color(self)
+            record.hit(&events, &hit);
+            if let Some(emitted) = hit.material.emission(&hit).filter(|_| !sampled) {
+                record_ending(record, &mut events, PathEvent::Light, throughput.clone() * emitted);
+            }
+            let material = hit.material.clone();
+            let geometric = hit.geometric;
+            let shading = hit.normal;
+            if let Some((wi, direct)) = ray.direct_light(world, &hit.pos, Some(&shading), |wi| material.eval_by_lobe(&hit, wi)) {
+                let through = wi.dot(&geometric) < 0.0;
+                for lobe in [Lobe::Diffuse, Lobe::Glossy] {
+                    events.push(PathEvent::Surface { lobe, through });
+                    record_ending(record, &mut events, PathEvent::Light, throughput.clone() * direct.get(lobe).clone());
+                    events.pop();
+                }
+            }
+            let Some(Bounce { ray: next, attenuation, lobe }) = material.scatter(hit) else {
+                return;
+            };
+            // a bent normal can send light through the true surface, which
+            // would leak light, so those paths are dropped
+            if (next.direction.dot(&geometric) > 0.0) != (next.direction.dot(&shading) > 0.0) {
+                return;
+            }
+            // light from inside a translucent object was sampled through its
+            // surface, so the way out doesn't change what was sampled
+            sampled = lobe != Lobe::Specular || (leaving && sampled);
+            record.scatter(&events, &attenuation);
+            throughput = throughput * attenuation;
+            events.push(PathEvent::Surface { lobe, through: next.direction.dot(&geometric) < 0.0 });
+            // the path keeps its wavelengths unless the material changed them
+            let wavelengths = if next.wavelengths == Wavelengths::Rgb { ray.wavelengths } else { next.wavelengths };
+            ray = next.with_wavelengths(wavelengths);
+            depth -= 1;
+        }
+    }
+    /// light reaching `pos` straight from one of the world's lights and the
+    /// direction it comes from, scaled by `response` to that direction.
+    /// None if there's no light or no response to it
+    fn direct_light(&self, world: &World, pos: &Vector, normal: Option<&Vector>, response: impl Fn(&Vector) -> ByLobe) -> Option<(Vector, ByLobe)> {
+        let incoming = world.sample_light(pos, normal, &self.wavelengths)?;
+        let response = response(&incoming.direction);
+        if response == ByLobe::NONE {
+            return None;
         }
+        let light = incoming.light.clone() * world.unblocked(pos, &incoming, &self.wavelengths);
+        Some((incoming.direction, response.map(|r| r * light.clone())))
     }
     pub fn cast(&self, world: &World, depth: usize) -> Color {
-        self.cast_inner(world, depth)
+        let mut light = Color::BLACK;
+        self.trace(world, depth, &mut light);
+        light
     }
     /// move the ray around a bit
     /// todo: this is a mess
     pub fn perturb(&self, scale_x: f32, scale_y: f32) -> Ray {
-        let mut rng = rand::thread_rng();
-        let dx: f32 = rng.gen();
-        let dy: f32 = rng.gen();
+        let dx = random();
+        let dy = random();
         Ray {
             origin: self.origin + Vector::new(dx * scale_x, dy * scale_y, 0.0),
             direction: self.direction,
+            ..*self
         }
         
     }
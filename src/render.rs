@@ -0,0 +1,146 @@
+use rayon::prelude::*;
+
+use crate::{
+    aov::{Aov, AovFilm, AovPixel, AovSample},
+    camera::Camera,
+    checkpoint::Checkpoint,
+    image::{Film, ImageBuffer},
+    integrator::Integrator,
+    numbers::{mix_seed, reseed, Samples},
+    spectrum::Wavelengths,
+    world::World,
+};
+
+/// everything that controls how an image is rendered, apart from the scene
+#[derive(Clone, Debug)]
+pub struct RenderSettings {
+    /// image width in pixels
+    pub width: usize,
+    /// image height in pixels
+    pub height: usize,
+    /// total samples per pixel, rounded up to whole passes
+    pub samples: usize,
+    /// samples traced for every pixel before moving on to the next pass
+    pub samples_per_pass: usize,
+    /// longest path traced before giving up on a sample
+    pub max_bounces: usize,
+    /// base seed for all random numbers; equal seeds give equal images
+    pub seed: u64,
+    /// trace hero wavelengths instead of red, green and blue, which is slower
+    /// and noisier but gets dispersion, thin films and colored lights right
+    pub spectral: bool,
+    /// what each camera ray shows, the lit scene or something about its surfaces
+    pub integrator: Integrator,
+}
+
+impl Default for RenderSettings {
+    fn default() -> RenderSettings {
+        RenderSettings {
+            width: 1024,
+            height: 1024 / 16 * 9,
+            samples: 100,
+            samples_per_pass: 10,
+            max_bounces: 1000,
+            seed: 0,
+            spectral: false,
+            integrator: Integrator::Path,
+        }
+    }
+}
+
+/// path traces a world through a camera, one pass at a time
+pub struct Renderer {
+    settings: RenderSettings,
+}
+
+impl Renderer {
+    pub fn new(settings: RenderSettings) -> Renderer {
+        Renderer { settings }
+    }
+    pub fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+    /// renders every pass and returns the finished image in linear color
+    pub fn render(&self, world: &World, camera: &Camera) -> ImageBuffer {
+        let mut state = self.start();
+        while state.passes < self.total_passes(&state) {
+            self.render_pass(world, camera, &mut state, || ());
+        }
+        state.film.to_image()
+    }
+    /// renders every pass like `render`, also returning each of the passes in `aovs`
+    pub fn render_with_aovs(&self, world: &World, camera: &Camera, aovs: &[Aov]) -> (ImageBuffer, Vec<(Aov, ImageBuffer)>) {
+        let mut state = self.start();
+        let mut film = AovFilm::new(self.settings.width, self.settings.height, aovs);
+        while state.passes < self.total_passes(&state) {
+            self.render_pass_with_aovs(world, camera, &mut state, &mut film, || ());
+        }
+        (state.film.to_image(), film.to_images())
+    }
+    /// a render state with no passes done yet
+    pub fn start(&self) -> Checkpoint {
+        Checkpoint {
+            seed: self.settings.seed,
+            samples_per_pass: self.settings.samples_per_pass as u32,
+            passes: 0,
+            spectral: self.settings.spectral,
+            film: Film::new(self.settings.width, self.settings.height),
+        }
+    }
+    /// number of passes needed to reach the requested samples,
+    /// using the pass size the state was started with
+    pub fn total_passes(&self, state: &Checkpoint) -> u32 {
+        self.settings.samples.div_ceil(state.samples_per_pass as usize) as u32
+    }
+    /// traces one more pass of samples for every pixel and adds them to the film,
+    /// calling `on_pixel` as each pixel finishes.
+    /// each pixel draws from its own generator seeded by the pass and pixel index,
+    /// so the result does not depend on how work is split between threads
+    pub fn render_pass(&self, world: &World, camera: &Camera, state: &mut Checkpoint, on_pixel: impl Fn() + Sync) {
+        self.pass(world, camera, state, None, on_pixel);
+    }
+    /// `render_pass`, also adding to the render passes in `aovs`. those aren't
+    /// kept in checkpoints, and only the path integrator fills them.
+    /// panics if `aovs` isn't the size of the state's film
+    pub fn render_pass_with_aovs(&self, world: &World, camera: &Camera, state: &mut Checkpoint, aovs: &mut AovFilm, on_pixel: impl Fn() + Sync) {
+        let size = (state.film.width(), state.film.height());
+        assert!(aovs.size() == size, "render passes of {:?} for a film of {:?}", aovs.size(), size);
+        self.pass(world, camera, state, Some(aovs), on_pixel);
+    }
+    fn pass(&self, world: &World, camera: &Camera, state: &mut Checkpoint, aovs: Option<&mut AovFilm>, on_pixel: impl Fn() + Sync) {
+        let Checkpoint { seed, samples_per_pass, passes, spectral, film } = state;
+        let (width, height) = (film.width(), film.height());
+        let max_bounces = self.settings.max_bounces;
+        let spectral = *spectral;
+        let integrator = self.settings.integrator;
+        let wanted = aovs.as_ref().map_or(Vec::new(), |aovs| aovs.aovs().to_vec());
+        let shade = |index: usize, pixel: &mut Samples, mut aov_pixel: Option<&mut AovPixel>| {
+            reseed(mix_seed(*seed, *passes, index));
+            let (i, j) = (index % width, index / width);
+            let samples: Samples = (0..*samples_per_pass)
+                .map(|_| {
+                    let ray = camera.ray(i, j, width, height);
+                    if integrator != Integrator::Path {
+                        return integrator.cast(&ray, world, max_bounces).sample();
+                    }
+                    let wavelengths = if spectral { Wavelengths::sample_hero() } else { Wavelengths::Rgb };
+                    let Some(aov_pixel) = aov_pixel.as_deref_mut().filter(|_| !wanted.is_empty()) else {
+                        return wavelengths.to_rgb(&ray.with_wavelengths(wavelengths).cast(world, max_bounces)).sample();
+                    };
+                    let mut sample = AovSample::new(&wanted, wavelengths);
+                    ray.with_wavelengths(wavelengths).trace(world, max_bounces, &mut sample);
+                    aov_pixel.add(&wanted, &sample);
+                    sample.beauty.sample()
+                })
+                .sum();
+            *pixel += samples;
+            on_pixel();
+        };
+        let pixels = film.samples_mut().par_iter_mut().enumerate();
+        match aovs {
+            Some(aovs) => pixels.zip(aovs.pixels_mut()).for_each(|((index, pixel), aov_pixel)| shade(index, pixel, Some(aov_pixel))),
+            None => pixels.for_each(|(index, pixel)| shade(index, pixel, None)),
+        }
+        *passes += 1;
+    }
+}
@@ -0,0 +1,140 @@
+use std::sync::Arc;
+
+use crate::{
+    instance::Instance,
+    material::Material,
+    numbers::Transform,
+    world::Hittable,
+};
+
+/// a named group in a scene graph. nodes carry a transform relative to their
+/// parent, the objects placed at them, and child nodes. a material set on a
+/// node is used by everything beneath it, unless a nearer node sets its own
+pub struct Node {
+    name: String,
+    transform: Transform,
+    material: Option<Arc<Material>>,
+    visible: bool,
+    objects: Vec<Arc<dyn Hittable>>,
+    children: Vec<Node>,
+}
+
+impl Node {
+    pub fn new(name: impl Into<String>) -> Node {
+        Node {
+            name: name.into(),
+            transform: Transform::IDENTITY,
+            material: None,
+            visible: true,
+            objects: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+    /// places this node relative to its parent
+    pub fn with_transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        self
+    }
+    /// draws everything beneath this node with the material, unless overridden further down
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = Some(Arc::new(material));
+        self
+    }
+    pub fn with_object(self, object: impl Hittable + 'static) -> Self {
+        self.with_shared_object(Arc::new(object))
+    }
+    /// places an object that may also be placed elsewhere, without copying it
+    pub fn with_shared_object(mut self, object: Arc<dyn Hittable>) -> Self {
+        self.objects.push(object);
+        self
+    }
+    pub fn with_child(mut self, child: Node) -> Self {
+        self.children.push(child);
+        self
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
+    }
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+    /// shows or hides this node and everything beneath it
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+    pub fn children(&self) -> &[Node] {
+        &self.children
+    }
+    /// looks up a descendant by a path of names separated by '/', like "car/wheels/front"
+    pub fn find(&self, path: &str) -> Option<&Node> {
+        path.split('/')
+            .filter(|name| !name.is_empty())
+            .try_fold(self, |node, name| node.children.iter().find(|c| c.name == name))
+    }
+    pub fn find_mut(&mut self, path: &str) -> Option<&mut Node> {
+        path.split('/')
+            .filter(|name| !name.is_empty())
+            .try_fold(self, |node, name| node.children.iter_mut().find(|c| c.name == name))
+    }
+    /// every visible object beneath this node as an instance in world space
+    pub fn flatten(&self) -> Vec<Instance> {
+        let mut instances = Vec::new();
+        self.flatten_into(&Transform::IDENTITY, None, &mut instances);
+        instances
+    }
+    fn flatten_into(&self, parent: &Transform, material: Option<&Arc<Material>>, out: &mut Vec<Instance>) {
+        if !self.visible {
+            return;
+        }
+        let transform = self.transform.then(parent);
+        let material = self.material.as_ref().or(material);
+        for object in &self.objects {
+            let instance = Instance::new(object.clone(), transform);
+            out.push(match material {
+                Some(material) => instance.with_shared_material(material.clone()),
+                None => instance,
+            });
+        }
+        for child in &self.children {
+            child.flatten_into(&transform, material, out);
+        }
+    }
+}
+
+#[test]
+fn test_scene_flatten() {
+    use crate::{numbers::Vector, ray::Ray, world::Sphere};
+    let ball = Arc::new(Sphere::new(0.0, 0.0, 0.0, 0.5));
+    let mut root = Node::new("root").with_transform(Transform::translate(0.0, 0.0, -5.0)).with_child(
+        Node::new("shelf")
+            .with_transform(Transform::translate(1.0, 0.0, 0.0))
+            .with_material(Material::TEST_METAL_RED)
+            .with_child(Node::new("left").with_shared_object(ball.clone()))
+            .with_child(
+                Node::new("right")
+                    .with_transform(Transform::translate(1.0, 0.0, 0.0))
+                    .with_material(Material::TEST_METAL_BLUE)
+                    .with_shared_object(ball),
+            ),
+    );
+    let instances = root.flatten();
+    assert!(instances.len() == 2);
+    let down_z = |x| Ray::new(Vector::new(x, 0.0, 0.0), Vector::Z_NEG);
+    let left = instances[0].hit(&down_z(1.0), 0.0, f32::INFINITY).unwrap();
+    assert!((left.length - 4.5).abs() < 1e-5);
+    assert!(matches!(*left.material, Material::Metal(_, ref c) if c.r() == 1.0));
+    let right = instances[1].hit(&down_z(2.0), 0.0, f32::INFINITY).unwrap();
+    assert!(matches!(*right.material, Material::Metal(_, ref c) if c.b() == 1.0));
+
+    root.find_mut("shelf/right").unwrap().set_visible(false);
+    assert!(root.flatten().len() == 1);
+    root.find_mut("shelf").unwrap().set_visible(false);
+    assert!(root.flatten().is_empty());
+    assert!(root.find("shelf/missing").is_none());
+}
@@ -0,0 +1,251 @@
+use std::sync::Arc;
+
+use crate::{
+    material::Material,
+    numbers::{Aabb, Transform, Vector},
+    ray::{Hit, Ray},
+    world::Hittable,
+};
+
+/// a shape described by its signed distance function: how far any point is
+/// from the surface, negative inside. combinators build bigger shapes from smaller ones
+#[derive(Clone, Debug)]
+pub enum Distance {
+    Sphere { center: Vector, radius: f32 },
+    Box { center: Vector, half: Vector },
+    /// lying flat around the y axis
+    Torus { center: Vector, major: f32, minor: f32 },
+    /// the mandelbulb fractal around the origin, roughly two units across
+    Mandelbulb { power: f32, iterations: usize },
+    Union(Box<Distance>, Box<Distance>),
+    Intersection(Box<Distance>, Box<Distance>),
+    /// the first with the second taken out of it
+    Subtraction(Box<Distance>, Box<Distance>),
+    /// a union with the seam rounded over roughly `k` units
+    SmoothUnion(Box<Distance>, Box<Distance>, f32),
+    /// a subtraction with the cut edge rounded over roughly `k` units
+    SmoothSubtraction(Box<Distance>, Box<Distance>, f32),
+    /// copies of the shape every `period` units along each axis; a zero period doesn't repeat
+    Repeat(Box<Distance>, Vector),
+    /// the shape moved by a transform, which must only rotate and translate
+    /// or distances would no longer be distances
+    Moved(Box<Distance>, Transform),
+}
+
+impl Distance {
+    pub fn sphere(center: Vector, radius: f32) -> Distance {
+        Distance::Sphere { center, radius }
+    }
+    pub fn cuboid(center: Vector, half: Vector) -> Distance {
+        Distance::Box { center, half }
+    }
+    pub fn torus(center: Vector, major: f32, minor: f32) -> Distance {
+        Distance::Torus { center, major, minor }
+    }
+    pub fn union(self, other: Distance) -> Distance {
+        Distance::Union(Box::new(self), Box::new(other))
+    }
+    pub fn intersection(self, other: Distance) -> Distance {
+        Distance::Intersection(Box::new(self), Box::new(other))
+    }
+    pub fn subtract(self, other: Distance) -> Distance {
+        Distance::Subtraction(Box::new(self), Box::new(other))
+    }
+    pub fn smooth_union(self, other: Distance, k: f32) -> Distance {
+        Distance::SmoothUnion(Box::new(self), Box::new(other), k)
+    }
+    pub fn smooth_subtract(self, other: Distance, k: f32) -> Distance {
+        Distance::SmoothSubtraction(Box::new(self), Box::new(other), k)
+    }
+    pub fn repeat(self, period: Vector) -> Distance {
+        Distance::Repeat(Box::new(self), period)
+    }
+    pub fn moved(self, transform: Transform) -> Distance {
+        Distance::Moved(Box::new(self), transform)
+    }
+    /// the signed distance from p to the surface
+    pub fn distance(&self, p: &Vector) -> f32 {
+        use Distance::*;
+        match self {
+            Sphere { center, radius } => (*p - *center).length() - radius,
+            Box { center, half } => {
+                let d = *p - *center;
+                let q = Vector::new(d.x.abs(), d.y.abs(), d.z.abs()) - *half;
+                let outside = q.max(&Vector::ORIGIN).length();
+                let inside = q.x.max(q.y).max(q.z).min(0.0);
+                outside + inside
+            }
+            Torus { center, major, minor } => {
+                let d = *p - *center;
+                let ring = (d.x * d.x + d.z * d.z).sqrt() - major;
+                (ring * ring + d.y * d.y).sqrt() - minor
+            }
+            Mandelbulb { power, iterations } => mandelbulb(p, *power, *iterations),
+            Union(a, b) => a.distance(p).min(b.distance(p)),
+            Intersection(a, b) => a.distance(p).max(b.distance(p)),
+            Subtraction(a, b) => a.distance(p).max(-b.distance(p)),
+            SmoothUnion(a, b, k) => {
+                let (a, b) = (a.distance(p), b.distance(p));
+                let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+                b + (a - b) * h - k * h * (1.0 - h)
+            }
+            SmoothSubtraction(a, b, k) => {
+                let (a, b) = (a.distance(p), b.distance(p));
+                let h = (0.5 - 0.5 * (a + b) / k).clamp(0.0, 1.0);
+                a + (-b - a) * h + k * h * (1.0 - h)
+            }
+            Repeat(shape, period) => {
+                let wrap = |x: f32, period: f32| if period == 0.0 { x } else { x - period * (x / period).round() };
+                let q = Vector::new(wrap(p.x, period.x), wrap(p.y, period.y), wrap(p.z, period.z));
+                shape.distance(&q)
+            }
+            Moved(shape, transform) => shape.distance(&transform.inverse().point(p)),
+        }
+    }
+}
+
+/// distance estimate for the mandelbulb, following the usual running-derivative method
+fn mandelbulb(p: &Vector, power: f32, iterations: usize) -> f32 {
+    let mut z = *p;
+    let mut dr = 1.0;
+    let mut r = z.length();
+    for _ in 0..iterations {
+        if r > 2.0 {
+            break;
+        }
+        let theta = (z.z / r).acos() * power;
+        let phi = z.y.atan2(z.x) * power;
+        dr = r.powf(power - 1.0) * power * dr + 1.0;
+        let zr = r.powf(power);
+        z = Vector::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()) * zr + *p;
+        r = z.length();
+    }
+    0.5 * r.ln() * r / dr
+}
+
+/// a distance function drawn by sphere tracing: stepping along the ray by
+/// the distance to the nearest surface until it is close enough to count as a hit
+pub struct Sdf {
+    shape: Distance,
+    bounds: Option<Aabb>,
+    material: Arc<Material>,
+    /// most steps taken along one ray before giving up
+    max_steps: usize,
+    /// how close to the surface counts as touching it
+    epsilon: f32,
+    /// how far an unbounded shape is traced before giving up
+    max_distance: f32,
+}
+
+impl Sdf {
+    /// a gray diffuse shape. tracing is limited to the bounds when there are any,
+    /// which is much faster, and shapes like repeated domains can leave them out
+    pub fn new(shape: Distance, bounds: Option<Aabb>) -> Sdf {
+        Sdf {
+            shape,
+            bounds,
+            material: Arc::new(Material::new()),
+            max_steps: 256,
+            epsilon: 1e-4,
+            max_distance: 100.0,
+        }
+    }
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = Arc::new(material);
+        self
+    }
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+    pub fn with_epsilon(mut self, epsilon: f32) -> Self {
+        self.epsilon = epsilon;
+        self
+    }
+    pub fn with_max_distance(mut self, max_distance: f32) -> Self {
+        self.max_distance = max_distance;
+        self
+    }
+    /// the surface normal at p, from central differences of the distance
+    pub fn normal(&self, p: &Vector) -> Vector {
+        let h = self.epsilon;
+        let d = |x, y, z| self.shape.distance(&(*p + Vector::new(x, y, z)));
+        Vector::new(d(h, 0.0, 0.0) - d(-h, 0.0, 0.0), d(0.0, h, 0.0) - d(0.0, -h, 0.0), d(0.0, 0.0, h) - d(0.0, 0.0, -h))
+            .unit()
+    }
+}
+
+impl Hittable for Sdf {
+    fn hit(&self, ray: &Ray, near: f32, far: f32) -> Option<Hit> {
+        let (start, end) = match &self.bounds {
+            Some(bounds) => bounds.clip(&ray.origin, &ray.direction, near, far)?,
+            None => (near, far),
+        };
+        // distances are measured in world units, lengths in multiples of the direction
+        let speed = ray.direction.length();
+        let end = end.min(start + self.max_distance / speed);
+        let mut length = start;
+        let mut steps = 0;
+        // a ray leaving a surface starts right on it, so move off it before
+        // deciding which side of the surface the ray is on
+        while self.shape.distance(&ray.at(length)).abs() < self.epsilon && steps < self.max_steps {
+            length += 2.0 * self.epsilon / speed;
+            steps += 1;
+        }
+        // rays that start inside trace the negated distance, toward the way out
+        let side = self.shape.distance(&ray.at(length)).signum();
+        for _ in steps..self.max_steps {
+            let distance = side * self.shape.distance(&ray.at(length));
+            if distance < self.epsilon {
+                if length < near {
+                    return None;
+                }
+                let pos = ray.at(length);
+                return Some(Hit::new(ray, length, pos, self.normal(&pos), &self.material));
+            }
+            length += distance / speed;
+            if length > end {
+                return None;
+            }
+        }
+        None
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        self.bounds
+    }
+}
+
+#[test]
+fn test_sdf_matches_sphere() {
+    use crate::world::Sphere;
+    let traced = Sdf::new(Distance::sphere(Vector::new(0.0, 0.0, -3.0), 1.0), None);
+    let exact = Sphere::new(0.0, 0.0, -3.0, 1.0);
+    for x in [0.0, 0.3, 0.6, 0.9] {
+        let ray = Ray::new(Vector::new(x, 0.1, 0.0), Vector::new(0.0, 0.0, -2.0));
+        let a = traced.hit(&ray, 0.0, f32::INFINITY).unwrap();
+        let b = exact.hit(&ray, 0.0, f32::INFINITY).unwrap();
+        assert!((a.length - b.length).abs() < 1e-3);
+        assert!((a.normal - b.normal).length() < 1e-2);
+    }
+    let miss = Ray::new(Vector::new(1.5, 0.0, 0.0), Vector::Z_NEG);
+    assert!(traced.hit(&miss, 0.0, f32::INFINITY).is_none());
+    // leaving the surface doesn't hit it again, going back in finds the far side
+    let surface = Vector::new(0.0, 0.0, -2.0);
+    assert!(traced.hit(&Ray::new(surface, Vector::Z_POS), 0.0, f32::INFINITY).is_none());
+    let through = traced.hit(&Ray::new(surface, Vector::Z_NEG), 0.0, f32::INFINITY).unwrap();
+    assert!((through.length - 2.0).abs() < 1e-3 && !through.front);
+}
+
+#[test]
+fn test_sdf_combinators() {
+    let a = Distance::sphere(Vector::new(-0.6, 0.0, 0.0), 1.0);
+    let b = Distance::sphere(Vector::new(0.6, 0.0, 0.0), 1.0);
+    let p = Vector::new(0.0, 1.0, 0.0);
+    let sharp = a.clone().union(b.clone()).distance(&p);
+    // smoothing fills in the crease between the spheres
+    assert!(a.clone().smooth_union(b.clone(), 0.5).distance(&p) < sharp);
+    assert!(a.clone().subtract(b.clone()).distance(&Vector::new(0.0, 0.0, 0.0)) > 0.0);
+    let grid = Distance::sphere(Vector::ORIGIN, 0.25).repeat(Vector::new(1.0, 0.0, 1.0));
+    assert!((grid.distance(&Vector::new(3.0, 0.0, -2.0)) + 0.25).abs() < 1e-5);
+    assert!(Distance::Mandelbulb { power: 8.0, iterations: 8 }.distance(&Vector::new(0.0, 0.0, 3.0)) > 0.0);
+}
@@ -0,0 +1,496 @@
+use std::{f32::consts::PI, sync::Arc};
+
+use crate::{
+    material::Material,
+    numbers::{roots_between, Aabb, Frame, Transform, Vector},
+    ray::{Hit, Ray},
+    world::{Hittable, Solid},
+};
+
+/// padding given to the bounds of flat shapes so their boxes are never empty
+const FLAT_PAD: f32 = 1e-4;
+
+/// the box around a disk, which is narrower along the axes its normal leans toward
+fn disk_bounds(center: Vector, normal: Vector, radius: f32) -> Aabb {
+    let extent = |n: f32| radius * (1.0 - n * n).max(0.0).sqrt() + FLAT_PAD;
+    let e = Vector::new(extent(normal.x), extent(normal.y), extent(normal.z));
+    Aabb::new(center - e, center + e)
+}
+
+/// angle around the z axis, scaled to 0-1
+fn turn(x: f32, y: f32) -> f32 {
+    (y.atan2(x) + PI) / (2.0 * PI)
+}
+
+/// an infinite flat surface
+pub struct Plane {
+    point: Vector,
+    frame: Frame,
+    material: Arc<Material>,
+}
+
+impl Plane {
+    /// a gray diffuse plane through the point, facing along the normal.
+    /// its texture coordinates are distances along the plane
+    pub fn new(point: Vector, normal: Vector) -> Plane {
+        let frame = Frame::from_z(normal.unit());
+        let material = Arc::new(Material::new());
+        Plane { point, frame, material }
+    }
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = Arc::new(material);
+        self
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, ray: &Ray, near: f32, far: f32) -> Option<Hit> {
+        let normal = self.frame.z;
+        let length = (self.point - ray.origin).dot(&normal) / ray.direction.dot(&normal);
+        if !(near..=far).contains(&length) {
+            return None;
+        }
+        let pos = ray.at(length);
+        let local = self.frame.to_local(&(pos - self.point));
+        let hit = Hit::new(ray, length, pos, normal, &self.material);
+        Some(hit.with_uv(local.x, local.y).with_tangent(self.frame.x))
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+/// a flat circle
+pub struct Disk {
+    center: Vector,
+    frame: Frame,
+    radius: f32,
+    material: Arc<Material>,
+}
+
+impl Disk {
+    /// a gray diffuse disk facing along the normal. texture coordinates go
+    /// around the edge and out from the center
+    pub fn new(center: Vector, normal: Vector, radius: f32) -> Disk {
+        let frame = Frame::from_z(normal.unit());
+        let material = Arc::new(Material::new());
+        Disk { center, frame, radius, material }
+    }
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = Arc::new(material);
+        self
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, ray: &Ray, near: f32, far: f32) -> Option<Hit> {
+        let normal = self.frame.z;
+        let length = (self.center - ray.origin).dot(&normal) / ray.direction.dot(&normal);
+        if !(near..=far).contains(&length) {
+            return None;
+        }
+        let pos = ray.at(length);
+        let local = self.frame.to_local(&(pos - self.center));
+        let distance = local.length();
+        if distance > self.radius {
+            return None;
+        }
+        let uv = (turn(local.x, local.y), distance / self.radius);
+        let around = self.frame.to_world(&Vector::new(-local.y, local.x, 0.0));
+        Some(Hit::new(ray, length, pos, normal, &self.material).with_uv(uv.0, uv.1).with_tangent(around))
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        Some(disk_bounds(self.center, self.frame.z, self.radius))
+    }
+}
+
+/// a flat parallelogram spanned by two edges from a corner
+pub struct Quad {
+    corner: Vector,
+    u: Vector,
+    v: Vector,
+    normal: Vector,
+    /// turns a point on the plane into coordinates along the edges
+    w: Vector,
+    material: Arc<Material>,
+}
+
+impl Quad {
+    /// a gray diffuse quad, facing toward u × v, with texture coordinates running along each edge
+    pub fn new(corner: Vector, u: Vector, v: Vector) -> Quad {
+        let n = u.cross(&v);
+        let w = n / n.dot(&n);
+        let material = Arc::new(Material::new());
+        Quad { corner, u, v, normal: n.unit(), w, material }
+    }
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = Arc::new(material);
+        self
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, near: f32, far: f32) -> Option<Hit> {
+        let length = (self.corner - ray.origin).dot(&self.normal) / ray.direction.dot(&self.normal);
+        if !(near..=far).contains(&length) {
+            return None;
+        }
+        let pos = ray.at(length);
+        let planar = pos - self.corner;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        let hit = Hit::new(ray, length, pos, self.normal, &self.material);
+        Some(hit.with_uv(alpha, beta).with_tangent(self.u))
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        let pad = Vector::new(FLAT_PAD, FLAT_PAD, FLAT_PAD);
+        let c = self.corner;
+        let b = Aabb::new(c, c + self.u + self.v)
+            .including(&(c + self.u))
+            .including(&(c + self.v));
+        Some(Aabb::new(b.min - pad, b.max + pad))
+    }
+}
+
+/// a rectangular box, either lined up with the world axes or turned
+pub struct Cuboid {
+    center: Vector,
+    half: Vector,
+    frame: Frame,
+    material: Arc<Material>,
+}
+
+impl Cuboid {
+    /// a gray diffuse box lined up with the world axes, spanning two opposite corners
+    pub fn new(a: Vector, b: Vector) -> Cuboid {
+        let bounds = Aabb::new(a, b);
+        Cuboid::oriented(bounds.center(), (bounds.max - bounds.min) * 0.5, &Transform::IDENTITY)
+    }
+    /// a box around the center, with the given half sizes along each axis,
+    /// turned by a transform which should only rotate
+    pub fn oriented(center: Vector, half: Vector, rotation: &Transform) -> Cuboid {
+        let frame = Frame::rotated(rotation);
+        let material = Arc::new(Material::new());
+        Cuboid { center, half, frame, material }
+    }
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = Arc::new(material);
+        self
+    }
+    /// the range of lengths along the ray spent inside the box
+    fn span(&self, ray: &Ray, near: f32, far: f32) -> Option<(f32, f32)> {
+        let origin = self.frame.to_local(&(ray.origin - self.center));
+        let direction = self.frame.to_local(&ray.direction);
+        Aabb::new(-self.half, self.half).clip(&origin, &direction, near, far)
+    }
+    /// the outward normal, tangent and texture coordinates of a point on the surface
+    fn surface(&self, pos: &Vector) -> (Vector, Vector, f32, f32) {
+        let local = self.frame.to_local(&(*pos - self.center));
+        let scaled = [local.x / self.half.x, local.y / self.half.y, local.z / self.half.z];
+        let axis = (0..3).fold(0, |best, a| if scaled[a].abs() > scaled[best].abs() { a } else { best });
+        let axes = [self.frame.x, self.frame.y, self.frame.z];
+        let normal = axes[axis] * scaled[axis].signum();
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        (normal, axes[u], 0.5 * (scaled[u] + 1.0), 0.5 * (scaled[v] + 1.0))
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, ray: &Ray, near: f32, far: f32) -> Option<Hit> {
+        // when the ray starts inside the box the entry is behind it, so the exit is the hit
+        let (enter, exit) = self.span(ray, f32::NEG_INFINITY, far)?;
+        let length = if enter >= near { enter } else { exit };
+        if !(near..=far).contains(&length) {
+            return None;
+        }
+        let pos = ray.at(length);
+        let (normal, tangent, u, v) = self.surface(&pos);
+        Some(Hit::new(ray, length, pos, normal, &self.material).with_uv(u, v).with_tangent(tangent))
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        let extent = |axis: usize| {
+            let a = [self.frame.x, self.frame.y, self.frame.z][axis];
+            Vector::new(a.x.abs(), a.y.abs(), a.z.abs()) * self.half.axis(axis)
+        };
+        let e = extent(0) + extent(1) + extent(2);
+        Some(Aabb::new(self.center - e, self.center + e))
+    }
+}
+
+/// both roots, smallest first, of a quadratic written as a t² + 2 half_b t + c
+fn quadratic(a: f32, half_b: f32, c: f32) -> Option<(f32, f32)> {
+    if a.abs() < 1e-12 {
+        if half_b == 0.0 {
+            return None;
+        }
+        let t = -c / (2.0 * half_b);
+        return Some((t, t));
+    }
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let sqrtd = discriminant.sqrt();
+    let (t0, t1) = ((-half_b - sqrtd) / a, (-half_b + sqrtd) / a);
+    Some((t0.min(t1), t0.max(t1)))
+}
+
+/// the length to where a ray crosses a circular cap at height z of a local frame
+fn cap(origin: &Vector, direction: &Vector, z: f32, radius: f32) -> Option<f32> {
+    let t = (z - origin.z) / direction.z;
+    let x = origin.x + t * direction.x;
+    let y = origin.y + t * direction.y;
+    if t.is_finite() && x * x + y * y <= radius * radius {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+/// a tube closed at both ends
+pub struct Cylinder {
+    base: Vector,
+    frame: Frame,
+    radius: f32,
+    height: f32,
+    material: Arc<Material>,
+}
+
+impl Cylinder {
+    /// a gray diffuse cylinder rising from the center of its base along the axis.
+    /// texture coordinates go around and up the side, and around and out on the caps
+    pub fn new(base: Vector, axis: Vector, radius: f32, height: f32) -> Cylinder {
+        let frame = Frame::from_z(axis.unit());
+        let material = Arc::new(Material::new());
+        Cylinder { base, frame, radius, height, material }
+    }
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = Arc::new(material);
+        self
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, ray: &Ray, near: f32, far: f32) -> Option<Hit> {
+        let o = self.frame.to_local(&(ray.origin - self.base));
+        let d = self.frame.to_local(&ray.direction);
+        let (r, h) = (self.radius, self.height);
+        let side = quadratic(d.x * d.x + d.y * d.y, o.x * d.x + o.y * d.y, o.x * o.x + o.y * o.y - r * r)
+            .map(|(t0, t1)| [t0, t1])
+            .unwrap_or([f32::NAN; 2]);
+        let candidates = side
+            .into_iter()
+            .filter(|t| (0.0..=h).contains(&(o.z + t * d.z)))
+            .map(|t| (t, 0))
+            .chain(cap(&o, &d, 0.0, r).map(|t| (t, 1)))
+            .chain(cap(&o, &d, h, r).map(|t| (t, 2)));
+        let (length, part) = candidates
+            .filter(|(t, _)| (near..=far).contains(t))
+            .min_by(|a, b| a.0.total_cmp(&b.0))?;
+        let p = o + d * length;
+        let (normal, u, v) = match part {
+            0 => (Vector::new(p.x, p.y, 0.0) / r, turn(p.x, p.y), p.z / h),
+            1 => (Vector::Z_NEG, turn(p.x, p.y), (p.x * p.x + p.y * p.y).sqrt() / r),
+            _ => (Vector::Z_POS, turn(p.x, p.y), (p.x * p.x + p.y * p.y).sqrt() / r),
+        };
+        let normal = self.frame.to_world(&normal);
+        let around = self.frame.to_world(&Vector::new(-p.y, p.x, 0.0));
+        Some(Hit::new(ray, length, ray.at(length), normal, &self.material).with_uv(u, v).with_tangent(around))
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        let top = self.base + self.frame.z * self.height;
+        Some(disk_bounds(self.base, self.frame.z, self.radius).union(&disk_bounds(top, self.frame.z, self.radius)))
+    }
+}
+
+/// a cone closed at its base
+pub struct Cone {
+    base: Vector,
+    frame: Frame,
+    radius: f32,
+    height: f32,
+    material: Arc<Material>,
+}
+
+impl Cone {
+    /// a gray diffuse cone with its tip at `height` along the axis from the center of its base.
+    /// texture coordinates go around and up the side, and around and out on the base
+    pub fn new(base: Vector, axis: Vector, radius: f32, height: f32) -> Cone {
+        let frame = Frame::from_z(axis.unit());
+        let material = Arc::new(Material::new());
+        Cone { base, frame, radius, height, material }
+    }
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = Arc::new(material);
+        self
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, ray: &Ray, near: f32, far: f32) -> Option<Hit> {
+        let o = self.frame.to_local(&(ray.origin - self.base));
+        let d = self.frame.to_local(&ray.direction);
+        let (r, h) = (self.radius, self.height);
+        // x² + y² = (slope (h - z))², with q the height left above the ray origin
+        let slope = r / h;
+        let s2 = slope * slope;
+        let q = h - o.z;
+        let side = quadratic(
+            d.x * d.x + d.y * d.y - s2 * d.z * d.z,
+            o.x * d.x + o.y * d.y + s2 * q * d.z,
+            o.x * o.x + o.y * o.y - s2 * q * q,
+        )
+        .map(|(t0, t1)| [t0, t1])
+        .unwrap_or([f32::NAN; 2]);
+        let candidates = side
+            .into_iter()
+            .filter(|t| (0.0..=h).contains(&(o.z + t * d.z)))
+            .map(|t| (t, true))
+            .chain(cap(&o, &d, 0.0, r).map(|t| (t, false)));
+        let (length, on_side) = candidates
+            .filter(|(t, _)| (near..=far).contains(t))
+            .min_by(|a, b| a.0.total_cmp(&b.0))?;
+        let p = o + d * length;
+        let radial = (p.x * p.x + p.y * p.y).sqrt();
+        let (normal, u, v) = if on_side {
+            (Vector::new(p.x, p.y, s2 * (h - p.z)).unit(), turn(p.x, p.y), p.z / h)
+        } else {
+            (Vector::Z_NEG, turn(p.x, p.y), radial / r)
+        };
+        let normal = self.frame.to_world(&normal);
+        let around = self.frame.to_world(&Vector::new(-p.y, p.x, 0.0));
+        Some(Hit::new(ray, length, ray.at(length), normal, &self.material).with_uv(u, v).with_tangent(around))
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        let tip = self.base + self.frame.z * self.height;
+        Some(disk_bounds(self.base, self.frame.z, self.radius).including(&tip))
+    }
+}
+
+/// a ring-shaped donut
+pub struct Torus {
+    center: Vector,
+    frame: Frame,
+    /// distance from the center to the middle of the tube
+    major: f32,
+    /// radius of the tube
+    minor: f32,
+    material: Arc<Material>,
+}
+
+impl Torus {
+    /// a gray diffuse torus lying flat around the axis. texture coordinates go
+    /// around the ring and then around the tube
+    pub fn new(center: Vector, axis: Vector, major: f32, minor: f32) -> Torus {
+        let frame = Frame::from_z(axis.unit());
+        let material = Arc::new(Material::new());
+        Torus { center, frame, major, minor, material }
+    }
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = Arc::new(material);
+        self
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, ray: &Ray, near: f32, far: f32) -> Option<Hit> {
+        // cull against the box first, the quartic is expensive
+        let (enter, exit) = self.bounds()?.clip(&ray.origin, &ray.direction, near, far)?;
+        let o = self.frame.to_local(&(ray.origin - self.center));
+        let d = self.frame.to_local(&ray.direction);
+        // solve along a unit direction, which keeps the coefficients well scaled
+        let scale = d.length();
+        let wide = |v: Vector| (v.x as f64, v.y as f64, v.z as f64);
+        let (o, d) = (wide(o), wide(d / scale));
+        let (big, small) = (self.major as f64, self.minor as f64);
+        // (|p|² + R² - r²)² = 4R²(x² + y²), with p = o + s d
+        let e = o.0 * o.0 + o.1 * o.1 + o.2 * o.2 + big * big - small * small;
+        let f = o.0 * d.0 + o.1 * d.1 + o.2 * d.2;
+        let k = 4.0 * big * big;
+        let coeffs = [
+            e * e - k * (o.0 * o.0 + o.1 * o.1),
+            4.0 * f * e - 2.0 * k * (o.0 * d.0 + o.1 * d.1),
+            2.0 * e + 4.0 * f * f - k * (d.0 * d.0 + d.1 * d.1),
+            4.0 * f,
+            1.0,
+        ];
+        let s = roots_between(&coeffs, (enter * scale) as f64, (exit * scale) as f64)
+            .into_iter()
+            .next()?;
+        let length = s as f32 / scale;
+        let p = Vector::new((o.0 + s * d.0) as f32, (o.1 + s * d.1) as f32, (o.2 + s * d.2) as f32);
+        let ring = Vector::new(p.x, p.y, 0.0).unit() * self.major;
+        let normal = self.frame.to_world(&((p - ring) / self.minor));
+        let radial = (p.x * p.x + p.y * p.y).sqrt() - self.major;
+        let (u, v) = (turn(p.x, p.y), turn(radial, p.z));
+        let around = self.frame.to_world(&Vector::new(-p.y, p.x, 0.0));
+        Some(Hit::new(ray, length, ray.at(length), normal, &self.material).with_uv(u, v).with_tangent(around))
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        let ring = disk_bounds(self.center, self.frame.z, self.major);
+        let pad = Vector::new(self.minor, self.minor, self.minor);
+        Some(Aabb::new(ring.min - pad, ring.max + pad))
+    }
+}
+
+impl Solid for Cuboid {}
+impl Solid for Cylinder {}
+impl Solid for Cone {}
+impl Solid for Torus {}
+
+#[test]
+fn test_flat_shapes() {
+    let down = Ray::new(Vector::new(0.5, 0.25, 5.0), Vector::Z_NEG);
+    let plane = Plane::new(Vector::new(0.0, 0.0, 1.0), Vector::Z_POS);
+    let hit = plane.hit(&down, 0.0, f32::INFINITY).unwrap();
+    assert!((hit.length - 4.0).abs() < 1e-5 && hit.front);
+    assert!(plane.bounds().is_none());
+
+    let disk = Disk::new(Vector::ORIGIN, Vector::Z_POS, 1.0);
+    let hit = disk.hit(&down, 0.0, f32::INFINITY).unwrap();
+    assert!((hit.uv.v - 0.3125f32.sqrt()).abs() < 1e-5);
+    assert!(Disk::new(Vector::ORIGIN, Vector::Z_POS, 0.5).hit(&down, 0.0, f32::INFINITY).is_none());
+
+    let quad = Quad::new(Vector::ORIGIN, Vector::new(2.0, 0.0, 0.0), Vector::new(0.0, 1.0, 0.0));
+    let hit = quad.hit(&down, 0.0, f32::INFINITY).unwrap();
+    assert!((hit.uv.u - 0.25).abs() < 1e-5 && (hit.uv.v - 0.25).abs() < 1e-5);
+    assert!((hit.normal - Vector::Z_POS).length() < 1e-5);
+}
+
+#[test]
+fn test_solid_shapes() {
+    let down = Ray::new(Vector::new(0.0, 0.0, 5.0), Vector::Z_NEG);
+    let boxed = Cuboid::new(Vector::new(-1.0, -1.0, -1.0), Vector::new(1.0, 1.0, 1.0));
+    assert!((boxed.hit(&down, 0.0, f32::INFINITY).unwrap().length - 4.0).abs() < 1e-5);
+    // from inside, the far wall is hit from behind
+    let inside = boxed.hit(&Ray::new(Vector::ORIGIN, Vector::Z_NEG), 0.0, f32::INFINITY).unwrap();
+    assert!((inside.length - 1.0).abs() < 1e-5 && !inside.front);
+    // turned 45 degrees, the corner edge pokes out toward the ray
+    let turned = Cuboid::oriented(Vector::ORIGIN, Vector::new(1.0, 1.0, 1.0), &Transform::rotate_y(PI / 4.0));
+    let hit = turned.hit(&down, 0.0, f32::INFINITY).unwrap();
+    assert!((hit.length - (5.0 - 2f32.sqrt())).abs() < 1e-4);
+    assert!((turned.bounds().unwrap().max.x - 2f32.sqrt()).abs() < 1e-5);
+
+    let side = Ray::new(Vector::new(5.0, 0.0, 0.5), Vector::new(-1.0, 0.0, 0.0));
+    let cylinder = Cylinder::new(Vector::ORIGIN, Vector::Z_POS, 1.0, 2.0);
+    let hit = cylinder.hit(&side, 0.0, f32::INFINITY).unwrap();
+    assert!((hit.length - 4.0).abs() < 1e-5 && (hit.uv.v - 0.25).abs() < 1e-5);
+    assert!((cylinder.hit(&down, 0.0, f32::INFINITY).unwrap().length - 3.0).abs() < 1e-5);
+
+    let cone = Cone::new(Vector::ORIGIN, Vector::Z_POS, 1.0, 2.0);
+    assert!((cone.hit(&side, 0.0, f32::INFINITY).unwrap().length - 4.25).abs() < 1e-5);
+    assert!((cone.hit(&down, 0.0, f32::INFINITY).unwrap().length - 3.0).abs() < 1e-5);
+
+    let torus = Torus::new(Vector::ORIGIN, Vector::Z_POS, 2.0, 0.5);
+    // straight down the hole misses, down through the tube hits the top of it
+    assert!(torus.hit(&down, 0.0, f32::INFINITY).is_none());
+    let tube = Ray::new(Vector::new(2.0, 0.0, 5.0), Vector::Z_NEG);
+    let hit = torus.hit(&tube, 0.0, f32::INFINITY).unwrap();
+    assert!((hit.length - 4.5).abs() < 1e-4);
+    assert!((hit.normal - Vector::Z_POS).length() < 1e-4);
+    let across = torus.hit(&Ray::new(Vector::new(-5.0, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0)), 0.0, f32::INFINITY);
+    assert!((across.unwrap().length - 2.5).abs() < 1e-4);
+}
@@ -0,0 +1,360 @@
+use std::sync::OnceLock;
+
+use crate::numbers::{random, Color};
+
+/// the shortest and longest wavelengths drawn for a path, in nanometres
+pub const VISIBLE: (f32, f32) = (380.0, 780.0);
+
+/// wavelengths standing in for the red, green and blue channels, in nanometres
+pub const RGB_WAVELENGTHS: [f32; 3] = [630.0, 532.0, 465.0];
+
+/// an index of refraction, which in real glass is higher for shorter wavelengths
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Ior {
+    /// the same at every wavelength
+    Constant(f32),
+    /// a + b / λ², with λ in micrometres
+    Cauchy { a: f32, b: f32 },
+    /// n² = 1 + Σ b λ² / (λ² - c), with λ in micrometres
+    Sellmeier { b: [f32; 3], c: [f32; 3] },
+}
+
+impl Ior {
+    /// borosilicate crown glass, common in lenses
+    pub const BK7: Ior = Ior::Sellmeier {
+        b: [1.039_612, 0.231_792_34, 1.010_469_4],
+        c: [0.006_000_699, 0.020_017_914, 103.560_65],
+    };
+    /// dense flint glass, which splits light much more than crown glass
+    pub const SF11: Ior = Ior::Sellmeier {
+        b: [1.737_597, 0.313_747_35, 1.898_781],
+        c: [0.013_188_707, 0.062_306_814, 155.236_3],
+    };
+    /// the index at a wavelength in nanometres
+    pub fn at(&self, wavelength: f32) -> f32 {
+        let micrometres = wavelength / 1000.0;
+        let l2 = micrometres * micrometres;
+        match self {
+            Ior::Constant(n) => *n,
+            Ior::Cauchy { a, b } => a + b / l2,
+            Ior::Sellmeier { b, c } => (1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f32>()).sqrt(),
+        }
+    }
+    /// whether the index changes with wavelength, so paths through it must pick one
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant(_))
+    }
+}
+
+impl From<f32> for Ior {
+    fn from(n: f32) -> Ior {
+        Ior::Constant(n)
+    }
+}
+
+/// a wavelength drawn evenly from the visible range
+pub fn sample_wavelength() -> f32 {
+    VISIBLE.0 + random() * (VISIBLE.1 - VISIBLE.0)
+}
+
+/// one lobe of the fit below, wider on one side than the other
+fn lobe(wavelength: f32, mean: f32, below: f32, above: f32) -> f32 {
+    let t = (wavelength - mean) / if wavelength < mean { below } else { above };
+    (-0.5 * t * t).exp()
+}
+
+/// the cie 1931 color matching functions, from the multi-lobe fit of
+/// wyman, sloan and shirley
+pub fn cie_xyz(wavelength: f32) -> [f32; 3] {
+    let l = wavelength;
+    let x = 1.056 * lobe(l, 599.8, 37.9, 31.0) + 0.362 * lobe(l, 442.0, 16.0, 26.7) - 0.065 * lobe(l, 501.1, 20.4, 26.2);
+    let y = 0.821 * lobe(l, 568.8, 46.9, 40.5) + 0.286 * lobe(l, 530.9, 16.3, 31.1);
+    let z = 1.217 * lobe(l, 437.0, 11.8, 36.0) + 0.681 * lobe(l, 459.0, 26.0, 13.8);
+    [x, y, z]
+}
+
+/// linear srgb from cie xyz
+pub fn xyz_to_rgb([x, y, z]: [f32; 3]) -> Color {
+    Color::new(
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    )
+}
+
+/// cie standard illuminant d65 every 10nm across the visible range, scaled to 1 at 560nm
+const D65: [f32; 41] = [
+    0.499755, 0.546482, 0.827549, 0.91486, 0.934318, 0.866823, 1.04865, 1.17008, 1.17812, 1.14861, 1.15923,
+    1.08811, 1.09354, 1.07802, 1.0479, 1.07689, 1.04405, 1.04046, 1.0, 0.963342, 0.95788, 0.886856, 0.900062,
+    0.895991, 0.876987, 0.832886, 0.836992, 0.800268, 0.802146, 0.822778, 0.782842, 0.697213, 0.716091, 0.74349,
+    0.61604, 0.698856, 0.75087, 0.635927, 0.464182, 0.668054, 0.633828,
+];
+
+/// average daylight, which srgb takes as white
+pub fn d65(wavelength: f32) -> f32 {
+    let t = ((wavelength - VISIBLE.0) / 10.0).clamp(0.0, 40.0);
+    let i = (t as usize).min(39);
+    D65[i] + (D65[i + 1] - D65[i]) * (t - i as f32)
+}
+
+/// light given off by a body this hot in kelvin, by planck's law, scaled to 1 at 560nm
+pub fn blackbody(kelvin: f32, wavelength: f32) -> f32 {
+    // second radiation constant, in nanometre kelvin
+    const C2: f64 = 1.438_776_9e7;
+    let planck = |l: f64| l.powi(-5) / ((C2 / (l * kelvin as f64)).exp() - 1.0);
+    (planck(wavelength as f64) / planck(560.0)) as f32
+}
+
+/// smits' spectra for white and the six primaries, in 10 even bins over 380-720nm
+const SMITS: [[f32; 10]; 7] = [
+    [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000],
+    [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000],
+    [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959],
+    [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840],
+    [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149],
+    [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025],
+    [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496],
+];
+
+/// the reflectance at a wavelength of a smooth spectrum with this rgb color, built
+/// by smits' method from white and the primaries it lies between. cheaper than
+/// fitting a spectrum per color, and white stays exactly flat
+pub fn upsample(rgb: &Color, wavelength: f32) -> f32 {
+    let bin = (((wavelength - 380.0) / 34.0) as usize).min(9);
+    let [white, cyan, magenta, yellow, red, green, blue] = SMITS.map(|s| s[bin]);
+    let (r, g, b) = (rgb.r(), rgb.g(), rgb.b());
+    if r <= g && r <= b {
+        r * white + if g <= b { (g - r) * cyan + (b - g) * blue } else { (b - r) * cyan + (g - b) * green }
+    } else if g <= r && g <= b {
+        g * white + if r <= b { (r - g) * magenta + (b - r) * blue } else { (b - g) * magenta + (r - b) * red }
+    } else {
+        b * white + if r <= g { (r - b) * yellow + (g - r) * green } else { (g - b) * yellow + (r - g) * red }
+    }
+}
+
+/// the srgb response to every visible wavelength of daylight summed, per channel
+fn rgb_totals() -> &'static [f32; 3] {
+    static TOTALS: OnceLock<[f32; 3]> = OnceLock::new();
+    TOTALS.get_or_init(|| {
+        let mut totals = [0.0; 3];
+        for step in VISIBLE.0 as usize..VISIBLE.1 as usize {
+            let wavelength = step as f32 + 0.5;
+            let rgb = xyz_to_rgb(cie_xyz(wavelength));
+            for (c, total) in totals.iter_mut().enumerate() {
+                *total += rgb.channel(c) * d65(wavelength);
+            }
+        }
+        totals
+    })
+}
+
+/// the srgb color that light of one wavelength drawn by `sample_wavelength`
+/// adds to the film, scaled so that daylight comes out white
+pub fn film_weight(wavelength: f32) -> Color {
+    let rgb = xyz_to_rgb(cie_xyz(wavelength));
+    let totals = rgb_totals();
+    let range = VISIBLE.1 - VISIBLE.0;
+    Color::new(rgb.r() * range / totals[0], rgb.g() * range / totals[1], rgb.b() * range / totals[2])
+}
+
+/// weight for a path narrowed from white light to one wavelength drawn by
+/// `sample_wavelength`, so that averaged over wavelengths it stays white.
+/// some wavelengths are outside srgb and weigh less than nothing in a channel
+pub fn wavelength_weight(wavelength: f32) -> Color {
+    film_weight(wavelength) * d65(wavelength)
+}
+
+/// what the three channels of the light a path carries stand for
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Wavelengths {
+    /// red, green and blue
+    #[default]
+    Rgb,
+    /// red, green and blue of white light that dispersion narrowed to one wavelength
+    Narrowed(f32),
+    /// the light at each of three wavelengths in nanometres, in spectral mode. the
+    /// first is the hero, which decides where dispersive glass bends the path
+    Hero([f32; 3]),
+}
+
+impl Wavelengths {
+    /// a hero wavelength drawn evenly, with the other two spread evenly after
+    /// it around the visible range so the three cover it together
+    pub fn sample_hero() -> Wavelengths {
+        let range = VISIBLE.1 - VISIBLE.0;
+        let hero = sample_wavelength();
+        Wavelengths::Hero([0.0, 1.0, 2.0].map(|i| VISIBLE.0 + (hero - VISIBLE.0 + i * range / 3.0) % range))
+    }
+    /// the one wavelength deciding how dispersive glass bends the path, if any
+    pub fn hero(&self) -> Option<f32> {
+        match self {
+            Wavelengths::Rgb => None,
+            Wavelengths::Narrowed(wavelength) => Some(*wavelength),
+            Wavelengths::Hero(wavelengths) => Some(wavelengths[0]),
+        }
+    }
+    /// the wavelength a channel carries
+    pub fn at(&self, channel: usize) -> f32 {
+        match self {
+            Wavelengths::Rgb => RGB_WAVELENGTHS[channel],
+            Wavelengths::Narrowed(wavelength) => *wavelength,
+            Wavelengths::Hero(wavelengths) => wavelengths[channel],
+        }
+    }
+    /// an rgb reflectance, like an albedo, as it applies to these channels
+    pub fn reflectance(&self, rgb: &Color) -> Color {
+        match self {
+            Wavelengths::Hero(w) => Color::new(upsample(rgb, w[0]), upsample(rgb, w[1]), upsample(rgb, w[2])),
+            _ => rgb.clone(),
+        }
+    }
+    /// rgb light, like the sky, as it applies to these channels. rgb white is daylight
+    pub fn illuminant(&self, rgb: &Color) -> Color {
+        match self {
+            Wavelengths::Hero(w) => self.reflectance(rgb) * Color::new(d65(w[0]), d65(w[1]), d65(w[2])),
+            _ => rgb.clone(),
+        }
+    }
+    /// a property known at `RGB_WAVELENGTHS`, like a metal's index of refraction,
+    /// at each channel's wavelength, in between the known ones and flat past them
+    pub fn interpolate(&self, rgb: &Color) -> Color {
+        let at = |wavelength: f32| {
+            let [red, green, blue] = RGB_WAVELENGTHS;
+            let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t.clamp(0.0, 1.0);
+            if wavelength >= green {
+                lerp(rgb.g(), rgb.r(), (wavelength - green) / (red - green))
+            } else {
+                lerp(rgb.b(), rgb.g(), (wavelength - blue) / (green - blue))
+            }
+        };
+        match self {
+            Wavelengths::Rgb => rgb.clone(),
+            Wavelengths::Narrowed(wavelength) => Color::gray(at(*wavelength)),
+            Wavelengths::Hero(w) => Color::new(at(w[0]), at(w[1]), at(w[2])),
+        }
+    }
+    /// the srgb color of light carried in these channels, for the film
+    pub fn to_rgb(&self, light: &Color) -> Color {
+        match self {
+            Wavelengths::Hero(w) => (0..3).fold(Color::BLACK, |sum, c| sum + film_weight(w[c]) * (light.channel(c) / 3.0)),
+            _ => light.clone(),
+        }
+    }
+}
+
+/// how bright light is at each wavelength, for anything that gives off light
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spectrum {
+    kind: Illuminant,
+    scale: f32,
+    /// the srgb color of the whole spectrum, worked out once for rgb rendering
+    rgb: Color,
+}
+
+/// the shapes of spectrum light can be given off in
+#[derive(Clone, Debug, PartialEq)]
+pub enum Illuminant {
+    /// an rgb color, where white is daylight
+    Rgb(Color),
+    /// a body glowing at this temperature in kelvin
+    Blackbody(f32),
+    /// cie standard illuminant d65, average daylight
+    D65,
+    /// cie standard illuminant a, a tungsten filament lamp
+    A,
+    /// the same energy at every wavelength
+    E,
+}
+
+impl Spectrum {
+    pub fn new(kind: Illuminant) -> Spectrum {
+        let rgb = match &kind {
+            Illuminant::Rgb(rgb) => rgb.clone(),
+            kind => {
+                // the average film weight over evenly spread wavelengths
+                let range = (VISIBLE.0 as usize..VISIBLE.1 as usize).map(|step| step as f32 + 0.5);
+                let total = range.fold(Color::BLACK, |sum, l| sum + film_weight(l) * Spectrum::shape(kind, l));
+                total * (VISIBLE.1 - VISIBLE.0).recip()
+            }
+        };
+        Spectrum { kind, scale: 1.0, rgb }
+    }
+    pub fn blackbody(kelvin: f32) -> Spectrum {
+        Spectrum::new(Illuminant::Blackbody(kelvin))
+    }
+    /// multiplies the brightness
+    pub fn with_scale(mut self, scale: f32) -> Spectrum {
+        self.scale = scale;
+        self
+    }
+    fn shape(kind: &Illuminant, wavelength: f32) -> f32 {
+        match kind {
+            Illuminant::Rgb(rgb) => upsample(rgb, wavelength) * d65(wavelength),
+            Illuminant::Blackbody(kelvin) => blackbody(*kelvin, wavelength),
+            Illuminant::D65 => d65(wavelength),
+            // standard illuminant a is defined as a blackbody
+            Illuminant::A => blackbody(2856.0, wavelength),
+            Illuminant::E => 1.0,
+        }
+    }
+    /// the brightness at a wavelength in nanometres
+    pub fn at(&self, wavelength: f32) -> f32 {
+        Spectrum::shape(&self.kind, wavelength) * self.scale
+    }
+    /// the srgb color of the light
+    pub fn rgb(&self) -> Color {
+        self.rgb.clone() * self.scale
+    }
+    /// the light as it applies to these channels
+    pub fn sample(&self, wavelengths: &Wavelengths) -> Color {
+        match (wavelengths, &self.kind) {
+            (Wavelengths::Hero(w), _) => Color::new(self.at(w[0]), self.at(w[1]), self.at(w[2])),
+            // narrowed paths already carry the color of daylight at their wavelength
+            (Wavelengths::Narrowed(l), kind) if !matches!(kind, Illuminant::Rgb(_)) => Color::gray(self.at(*l) / d65(*l)),
+            _ => self.rgb(),
+        }
+    }
+}
+
+impl From<Color> for Spectrum {
+    fn from(rgb: Color) -> Spectrum {
+        Spectrum::new(Illuminant::Rgb(rgb))
+    }
+}
+
+#[test]
+fn test_wavelengths_average_to_white() {
+    use crate::numbers::reseed;
+    reseed(6);
+    let runs = 100000;
+    let total = (0..runs).fold(Color::BLACK, |sum, _| sum + wavelength_weight(sample_wavelength()));
+    for c in 0..3 {
+        assert!((total.channel(c) / runs as f32 - 1.0).abs() < 0.03);
+    }
+    // red light is red, and glass bends blue light more
+    assert!(wavelength_weight(650.0).r() > wavelength_weight(650.0).b());
+    assert!(Ior::BK7.at(450.0) > Ior::BK7.at(650.0));
+    assert!((Ior::BK7.at(587.6) - 1.5168).abs() < 1e-3);
+}
+
+#[test]
+fn test_spectra_match_their_colors() {
+    // averaged over the wavelengths, upsampled daylight colors come back
+    // as the colors they came from
+    let colors = [Color::WHITE, Color::new(0.8, 0.2, 0.1), Color::new(0.1, 0.5, 0.9), Color::gray(0.3)];
+    for rgb in colors {
+        let spectrum = Spectrum::new(Illuminant::Rgb(rgb.clone()));
+        let range = (VISIBLE.0 as usize..VISIBLE.1 as usize).map(|step| step as f32 + 0.5);
+        let total = range.fold(Color::BLACK, |sum, l| sum + film_weight(l) * spectrum.at(l));
+        let back = total * (VISIBLE.1 - VISIBLE.0).recip();
+        for c in 0..3 {
+            assert!((back.channel(c) - rgb.channel(c)).abs() < 0.06);
+        }
+    }
+    // daylight is white, and a candle is redder than a blue sky
+    let daylight = Spectrum::new(Illuminant::D65).rgb();
+    assert!((daylight.r() - 1.0).abs() < 0.01 && (daylight.b() - 1.0).abs() < 0.01);
+    let candle = Spectrum::blackbody(1900.0).rgb();
+    let sky = Spectrum::blackbody(12000.0).rgb();
+    assert!(candle.r() > candle.b() && sky.b() > sky.r());
+}
@@ -0,0 +1,192 @@
+use std::{
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+use crate::{
+    image::ImageBuffer,
+    numbers::{float_bits, Color, Uv, Vector},
+    ray::Hit,
+};
+
+/// how far apart in uv heights are compared to find the slope of a bump map
+const BUMP_STEP: f32 = 1e-3;
+
+/// a color that varies over a surface with its texture coordinates.
+/// textures driving a single number use the average of the channels
+#[derive(Clone)]
+pub enum Texture {
+    Constant(Color),
+    /// squares alternating between two textures, `scale` of them across each unit of uv
+    Checker { scale: f32, even: Box<Texture>, odd: Box<Texture> },
+    /// an image stretched over the unit square of uv, with v going up from the
+    /// bottom row, repeating outside it and blended between pixels
+    Image(Arc<ImageBuffer>),
+}
+
+impl From<Color> for Texture {
+    fn from(color: Color) -> Texture {
+        Texture::Constant(color)
+    }
+}
+
+impl From<f32> for Texture {
+    fn from(value: f32) -> Texture {
+        Texture::Constant(Color::gray(value))
+    }
+}
+
+impl Texture {
+    pub fn checker(scale: f32, even: impl Into<Texture>, odd: impl Into<Texture>) -> Texture {
+        Texture::Checker { scale, even: Box::new(even.into()), odd: Box::new(odd.into()) }
+    }
+    pub fn image(image: Arc<ImageBuffer>) -> Texture {
+        Texture::Image(image)
+    }
+    /// the color at a point on the surface
+    pub fn color(&self, uv: &Uv) -> Color {
+        match self {
+            Texture::Constant(color) => color.clone(),
+            Texture::Checker { scale, even, odd } => {
+                let square = (uv.u * scale).floor() + (uv.v * scale).floor();
+                if square.rem_euclid(2.0) < 1.0 {
+                    even.color(uv)
+                } else {
+                    odd.color(uv)
+                }
+            }
+            Texture::Image(image) => bilinear(image, uv),
+        }
+    }
+    /// the single number at a point on the surface
+    pub fn value(&self, uv: &Uv) -> f32 {
+        self.color(uv).average()
+    }
+}
+
+/// by what's drawn, so textures that look the same hash alike from run to run.
+/// images are only sampled, since every hit of a material id hashes them
+impl Hash for Texture {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Texture::Constant(color) => color.hash(state),
+            Texture::Checker { scale, even, odd } => {
+                float_bits(*scale).hash(state);
+                even.hash(state);
+                odd.hash(state);
+            }
+            Texture::Image(image) => {
+                (image.width(), image.height(), image.channels()).hash(state);
+                let pixels = image.pixels();
+                for pixel in pixels.iter().step_by((pixels.len() / 64).max(1)) {
+                    pixel.hash(state);
+                }
+            }
+        }
+    }
+}
+
+/// bends the shading normal of a surface to fake detail too small to model
+#[derive(Clone)]
+pub enum Bump {
+    /// a tangent-space normal map: red, green and blue from 0 to 1 give the normal
+    /// from -1 to 1 along the tangent, the other direction across the surface, and
+    /// the unbent normal, so flat is (0.5, 0.5, 1). read images for these as stored
+    Normal(Texture),
+    /// a height map, with the surface sloping where the height changes.
+    /// `scale` is how high a value of 1 is compared to a unit of uv
+    Height { height: Texture, scale: f32 },
+}
+
+impl Hash for Bump {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Bump::Normal(map) => map.hash(state),
+            Bump::Height { height, scale } => {
+                height.hash(state);
+                float_bits(*scale).hash(state);
+            }
+        }
+    }
+}
+
+impl Bump {
+    /// the shading normal of the hit bent by the map, still facing back along the ray
+    pub fn normal(&self, hit: &Hit) -> Vector {
+        // bend the outward normal, so maps look the same from both sides
+        let side = if hit.front { 1.0 } else { -1.0 };
+        let mut outward = hit.clone();
+        outward.normal = hit.normal * side;
+        let frame = outward.shading_frame();
+        let bent = match self {
+            Bump::Normal(map) => {
+                let c = map.color(&hit.uv);
+                let local = Vector::new(2.0 * c.r() - 1.0, 2.0 * c.g() - 1.0, 2.0 * c.b() - 1.0);
+                frame.to_world(&local)
+            }
+            Bump::Height { height, scale } => {
+                let Uv { u, v } = hit.uv;
+                let h = height.value(&hit.uv);
+                let du = (height.value(&Uv::new(u + BUMP_STEP, v)) - h) / BUMP_STEP;
+                let dv = (height.value(&Uv::new(u, v + BUMP_STEP)) - h) / BUMP_STEP;
+                frame.z - (frame.x * du + frame.y * dv) * *scale
+            }
+        };
+        if bent.square_length() < 1e-12 {
+            return hit.normal;
+        }
+        bent.unit() * side
+    }
+}
+
+/// blends the four pixels around a point, treating pixel centers as sitting at half steps
+fn bilinear(image: &ImageBuffer, uv: &Uv) -> Color {
+    let (width, height) = (image.width(), image.height());
+    if width == 0 || height == 0 {
+        return Color::BLACK;
+    }
+    let x = uv.u * width as f32 - 0.5;
+    let y = (1.0 - uv.v) * height as f32 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (x - x0, y - y0);
+    let wrap = |i: f32, size: usize| (i as i64).rem_euclid(size as i64) as usize;
+    let at = |dx: f32, dy: f32| image.pixel(wrap(x0 + dx, width), wrap(y0 + dy, height)).clone();
+    let top = at(0.0, 0.0) * (1.0 - tx) + at(1.0, 0.0) * tx;
+    let bottom = at(0.0, 1.0) * (1.0 - tx) + at(1.0, 1.0) * tx;
+    top * (1.0 - ty) + bottom * ty
+}
+
+#[test]
+fn test_textures() {
+    let checker = Texture::checker(2.0, Color::WHITE, 0.0);
+    assert!(checker.value(&Uv::new(0.1, 0.1)) == 1.0);
+    assert!(checker.value(&Uv::new(0.6, 0.1)) == 0.0);
+    assert!(checker.value(&Uv::new(-0.1, 0.1)) == 0.0);
+    let pixels = vec![Color::BLACK, Color::WHITE, Color::WHITE, Color::BLACK];
+    let image = Texture::image(Arc::new(ImageBuffer::from_pixels(2, 2, pixels).unwrap()));
+    // the bottom left pixel's center, and halfway between two pixel centers
+    assert!(image.value(&Uv::new(0.25, 0.25)) == 1.0);
+    assert!((image.value(&Uv::new(0.5, 0.75)) - 0.5).abs() < 1e-6);
+}
+
+#[test]
+fn test_bump_normals() {
+    use crate::{material::Material, ray::Ray};
+    let material = Arc::new(Material::new());
+    let ray = Ray::new(Vector::new(0.0, 0.0, 1.0), Vector::Z_NEG);
+    let hit = Hit::new(&ray, 1.0, Vector::ORIGIN, Vector::Z_POS, &material)
+        .with_uv(0.5, 0.5)
+        .with_tangent(Vector::new(1.0, 0.0, 0.0));
+    // a flat normal map and a flat height map leave the normal alone
+    let flat = Bump::Normal(Color::new(0.5, 0.5, 1.0).into());
+    assert!((flat.normal(&hit) - Vector::Z_POS).length() < 1e-5);
+    assert!((Bump::Height { height: 0.3.into(), scale: 1.0 }.normal(&hit) - Vector::Z_POS).length() < 1e-5);
+    // leaning toward the tangent, from either side
+    let leaning = Bump::Normal(Color::new(1.0, 0.5, 0.5).into());
+    assert!((leaning.normal(&hit) - Vector::new(1.0, 0.0, 0.0)).length() < 1e-5);
+    let behind = Hit::new(&Ray::new(Vector::new(0.0, 0.0, -1.0), Vector::Z_POS), 1.0, Vector::ORIGIN, Vector::Z_POS, &material)
+        .with_tangent(Vector::new(1.0, 0.0, 0.0));
+    assert!((leaning.normal(&behind) - Vector::new(-1.0, 0.0, 0.0)).length() < 1e-5);
+}
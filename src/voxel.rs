@@ -0,0 +1,312 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, ErrorKind, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    medium::{Collision, Event, Participating},
+    numbers::{random, Aabb, Color, Vector},
+    ray::Ray,
+};
+
+/// identifies a voxel grid file
+const MAGIC: &[u8; 4] = b"WRVG";
+/// bumped whenever the layout below changes
+const VERSION: u32 = 1;
+/// the bytes before the voxels
+const HEADER_BYTES: u64 = 24;
+
+/// a dense grid of voxels, each holding a density and optionally
+/// the color of the light it gives off
+///
+/// file layout, all values little-endian:
+/// magic, version, x, y and z sizes, channels (1 for density only, 4 with emission),
+/// then for every voxel, x fastest and z slowest, density and then r, g, b emission (f32)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Grid {
+    size: [usize; 3],
+    density: Vec<f32>,
+    emission: Option<Vec<Color>>,
+}
+
+impl Grid {
+    /// a grid of densities, x fastest and z slowest, or None if the sizes don't
+    /// match or any of them is 0
+    pub fn new(size: [usize; 3], density: Vec<f32>) -> Option<Grid> {
+        (voxel_count(size) == Some(density.len())).then_some(Grid { size, density, emission: None })
+    }
+    /// adds light given off by every voxel, or None if the sizes don't match
+    pub fn with_emission(mut self, emission: Vec<Color>) -> Option<Grid> {
+        (emission.len() == self.density.len()).then(|| {
+            self.emission = Some(emission);
+            self
+        })
+    }
+    pub fn size(&self) -> [usize; 3] {
+        self.size
+    }
+    pub fn max_density(&self) -> f32 {
+        self.density.iter().fold(0.0, |max, d| max.max(*d))
+    }
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        x + self.size[0] * (y + self.size[1] * z)
+    }
+    /// blends the eight voxels around a point measured in voxels, where voxel
+    /// centers sit at half steps. everything outside the grid is empty, and
+    /// the outer half voxel inside it takes the value of the edge
+    fn trilinear<T>(&self, p: &Vector, empty: T, value: impl Fn(usize) -> T) -> T
+    where
+        T: std::ops::Add<Output = T> + std::ops::Mul<f32, Output = T>,
+    {
+        let p = [p.x, p.y, p.z];
+        if (0..3).any(|axis| !(0.0..=self.size[axis] as f32).contains(&p[axis])) {
+            return empty;
+        }
+        let q = p.map(|c| c - 0.5);
+        let base = q.map(|c| c.floor());
+        let t = [q[0] - base[0], q[1] - base[1], q[2] - base[2]];
+        let mut sum = empty;
+        for corner in 0..8 {
+            let offset = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+            let mut weight = 1.0;
+            let mut at = [0; 3];
+            for axis in 0..3 {
+                let i = base[axis] as i64 + offset[axis] as i64;
+                at[axis] = i.clamp(0, self.size[axis] as i64 - 1) as usize;
+                weight *= if offset[axis] == 1 { t[axis] } else { 1.0 - t[axis] };
+            }
+            if weight > 0.0 {
+                sum = sum + value(self.index(at[0], at[1], at[2])) * weight;
+            }
+        }
+        sum
+    }
+    /// the density at a point measured in voxels
+    pub fn density(&self, p: &Vector) -> f32 {
+        self.trilinear(p, 0.0, |i| self.density[i])
+    }
+    /// the light given off at a point measured in voxels
+    pub fn emission(&self, p: &Vector) -> Color {
+        match &self.emission {
+            Some(emission) => self.trilinear(p, Color::BLACK, |i| emission[i].clone()),
+            None => Color::BLACK,
+        }
+    }
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        for size in self.size {
+            out.write_all(&(size as u32).to_le_bytes())?;
+        }
+        let channels: u32 = if self.emission.is_some() { 4 } else { 1 };
+        out.write_all(&channels.to_le_bytes())?;
+        for (i, density) in self.density.iter().enumerate() {
+            out.write_all(&density.to_le_bytes())?;
+            if let Some(emission) = &self.emission {
+                for c in 0..3 {
+                    out.write_all(&emission[i].channel(c).to_le_bytes())?;
+                }
+            }
+        }
+        out.flush()
+    }
+    pub fn load(path: &Path) -> io::Result<Grid> {
+        let invalid = |message: String| io::Error::new(ErrorKind::InvalidData, message);
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+        let mut input = BufReader::new(file);
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a voxel grid file".into()));
+        }
+        let version = read_u32(&mut input)?;
+        if version != VERSION {
+            return Err(invalid(format!("unsupported voxel grid version {}", version)));
+        }
+        let size = [read_u32(&mut input)? as usize, read_u32(&mut input)? as usize, read_u32(&mut input)? as usize];
+        let channels = read_u32(&mut input)?;
+        if channels != 1 && channels != 4 {
+            return Err(invalid(format!("unsupported voxel grid channels {}", channels)));
+        }
+        // check the header against the file before trusting it with an allocation
+        let count = voxel_count(size)
+            .filter(|&count| (count as u64).saturating_mul(4 * channels as u64) <= length.saturating_sub(HEADER_BYTES))
+            .ok_or_else(|| invalid(format!("bad or truncated voxel grid of size {:?}", size)))?;
+        let mut density = Vec::with_capacity(count);
+        let mut emission = Vec::with_capacity(if channels == 4 { count } else { 0 });
+        for _ in 0..count {
+            density.push(f32::from_bits(read_u32(&mut input)?));
+            if channels == 4 {
+                let r = f32::from_bits(read_u32(&mut input)?);
+                let g = f32::from_bits(read_u32(&mut input)?);
+                let b = f32::from_bits(read_u32(&mut input)?);
+                emission.push(Color::new(r, g, b));
+            }
+        }
+        let grid = Grid { size, density, emission: None };
+        Ok(if channels == 4 { Grid { emission: Some(emission), ..grid } } else { grid })
+    }
+}
+
+/// how many voxels a grid of this size holds, or None if it's empty or too big
+fn voxel_count(size: [usize; 3]) -> Option<usize> {
+    let count = size[0].checked_mul(size[1])?.checked_mul(size[2])?;
+    (count > 0).then_some(count)
+}
+
+fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// a medium whose density varies through space, like a cloud or an explosion,
+/// read from a voxel grid stretched over a box. density is gray; the albedo
+/// tints what scatters, and voxels with emission glow like fire
+pub struct VoxelMedium {
+    grid: Grid,
+    bounds: Aabb,
+    /// extinction per unit of distance where the grid's density is 1
+    scale: f32,
+    albedo: Color,
+    g: f32,
+    /// brightness of the grid's emission
+    emission: f32,
+    /// extinction bounding the whole grid from above, for tracking
+    majorant: f32,
+}
+
+impl VoxelMedium {
+    /// a white, evenly scattering medium filling the box with the grid
+    pub fn new(grid: Grid, bounds: Aabb) -> VoxelMedium {
+        let majorant = grid.max_density();
+        VoxelMedium { grid, bounds, scale: 1.0, albedo: Color::WHITE, g: 0.0, emission: 1.0, majorant }
+    }
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self.majorant = self.grid.max_density() * scale;
+        self
+    }
+    pub fn with_albedo(mut self, albedo: Color) -> Self {
+        self.albedo = albedo;
+        self
+    }
+    pub fn with_g(mut self, g: f32) -> Self {
+        self.g = g;
+        self
+    }
+    pub fn with_emission(mut self, emission: f32) -> Self {
+        self.emission = emission;
+        self
+    }
+    /// where a world position falls in the grid, measured in voxels
+    fn to_grid(&self, p: &Vector) -> Vector {
+        let extent = self.bounds.max - self.bounds.min;
+        let [x, y, z] = self.grid.size().map(|s| s as f32);
+        let local = *p - self.bounds.min;
+        Vector::new(local.x / extent.x * x, local.y / extent.y * y, local.z / extent.z * z)
+    }
+    /// extinction per unit of distance at a world position
+    pub fn extinction(&self, p: &Vector) -> f32 {
+        self.grid.density(&self.to_grid(p)) * self.scale
+    }
+    /// steps from one tentative collision to the next with the majorant,
+    /// calling `step` with each until it says to stop or the ray passes `end`
+    fn track<T>(&self, ray: &Ray, start: f32, end: f32, mut step: impl FnMut(f32, f32) -> Option<T>) -> Option<T> {
+        if self.majorant <= 0.0 {
+            return None;
+        }
+        let speed = ray.direction.length();
+        let mut length = start;
+        loop {
+            length += -(1.0 - random()).ln() / (self.majorant * speed);
+            if length >= end {
+                return None;
+            }
+            let fraction = self.extinction(&ray.at(length)) / self.majorant;
+            if let Some(done) = step(length, fraction) {
+                return Some(done);
+            }
+        }
+    }
+}
+
+impl Participating for VoxelMedium {
+    /// delta tracking: tentative collisions with the majorant are real with the
+    /// chance of the local extinction, and rejected ones are null collisions
+    fn sample(&self, ray: &Ray, start: f32, end: f32) -> Event {
+        let collision = self.track(ray, start, end, |length, fraction| {
+            (random() < fraction).then(|| {
+                let pos = ray.at(length);
+                // expected over absorbing, which ends the path with the emission,
+                // and scattering, which carries on
+                let albedo = ray.wavelengths.reflectance(&self.albedo);
+                let glow = ray.wavelengths.illuminant(&(self.grid.emission(&self.to_grid(&pos)) * self.emission));
+                let emission = albedo.map(|a| 1.0 - a) * glow;
+                Collision { length, weight: albedo, emission, g: self.g }
+            })
+        });
+        match collision {
+            Some(collision) => Event::Scatter(collision),
+            None => Event::Pass { weight: Color::WHITE },
+        }
+    }
+    /// delta tracking lets light through exactly as often as the medium does
+    fn pass_weight(&self, _ray: &Ray, _start: f32, _end: f32) -> Color {
+        Color::WHITE
+    }
+    /// ratio tracking: every tentative collision keeps the chance of it being null
+    fn transmittance_between(&self, ray: &Ray, start: f32, end: f32) -> Color {
+        let mut transmittance = 1.0;
+        self.track(ray, start, end, |_, fraction| {
+            transmittance *= 1.0 - fraction.min(1.0);
+            None::<()>
+        });
+        Color::gray(transmittance)
+    }
+}
+
+#[test]
+fn test_grid_round_trip() {
+    let grid = Grid::new([2, 1, 2], vec![0.0, 1.0, 2.0, 3.0])
+        .unwrap()
+        .with_emission(vec![Color::RED, Color::BLACK, Color::WHITE, Color::BLUE])
+        .unwrap();
+    // between the centers of the first two voxels
+    assert!((grid.density(&Vector::new(1.0, 0.5, 0.5)) - 0.5).abs() < 1e-6);
+    let path = std::env::temp_dir().join(format!("voxel-test-{}.bin", std::process::id()));
+    grid.save(&path).unwrap();
+    let loaded = Grid::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(loaded == grid);
+    assert!(Grid::new([0, 4, 4], Vec::new()).is_none());
+    // a header promising far more voxels than the file holds
+    let mut bytes = MAGIC.to_vec();
+    for value in [VERSION, u32::MAX, u32::MAX, 2, 1] {
+        bytes.extend(value.to_le_bytes());
+    }
+    std::fs::write(&path, bytes).unwrap();
+    assert_eq!(Grid::load(&path).unwrap_err().kind(), ErrorKind::InvalidData);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_tracking_matches_uniform() {
+    use crate::numbers::reseed;
+    reseed(3);
+    // a grid of one density everywhere is a uniform medium, so both trackers
+    // should let through exp(-extinction * distance)
+    let grid = Grid::new([4, 4, 4], vec![1.0; 64]).unwrap();
+    let bounds = Aabb::new(Vector::new(-1.0, -1.0, -1.0), Vector::new(1.0, 1.0, 1.0));
+    let cloud = VoxelMedium::new(grid, bounds).with_scale(0.8);
+    let ray = Ray::new(Vector::new(0.0, 0.0, 0.9), Vector::new(0.0, 0.0, -1.0));
+    let expected = (-0.8f32 * 1.5).exp();
+    let runs = 20000;
+    let passed = (0..runs).filter(|_| matches!(cloud.sample(&ray, 0.1, 1.6), Event::Pass { .. })).count();
+    assert!((passed as f32 / runs as f32 - expected).abs() < 0.02);
+    let ratio = (0..runs).map(|_| cloud.transmittance_between(&ray, 0.1, 1.6).r()).sum::<f32>() / runs as f32;
+    assert!((ratio - expected).abs() < 0.02);
+}
@@ -1,44 +1,296 @@
-use std::{cmp::Ordering, rc::Rc, sync::Arc};
+use std::{
+    cmp::Ordering,
+    f32::consts::PI,
+    sync::{Arc, OnceLock},
+};
 
 use crate::{
+    bvh::Bvh,
+    csg::Csg,
     material::Material,
-    numbers::{Color, Vector},
+    medium::{sample_media, Event, Medium, Participating, Volume},
+    mesh::Mesh,
+    numbers::{random, Aabb, Color, Vector},
     ray::{Hit, Ray},
+    scene::Node,
+    shapes::Plane,
+    spectrum::{Spectrum, Wavelengths},
+    light::{Incoming, Light},
+    light_tree::{LightPicker, LightSampling},
 };
 
+/// how far a ray travels before it can hit anything, so rays leaving a surface
+/// don't hit that same surface again because of rounding
+pub const SURFACE_GAP: f32 = 1e-4;
+
+/// anything a ray can hit
+pub trait Hittable: Send + Sync {
+    /// the nearest hit with a length between near and far
+    fn hit(&self, ray: &Ray, near: f32, far: f32) -> Option<Hit>;
+    /// a box around the whole object, or None if it goes on forever
+    fn bounds(&self) -> Option<Aabb>;
+}
+
+/// a stretch of a ray spent inside a solid, between the hit going in and the hit coming out
+#[derive(Clone)]
+pub struct Span {
+    pub enter: Hit,
+    pub exit: Hit,
+}
+
+/// a closed object with a well defined inside
+pub trait Solid: Hittable {
+    /// every stretch of the whole ray, forward and backward, that lies inside the solid, in order.
+    /// by default this walks along the ray from hit to hit, pairing each hit on the
+    /// front of the surface with the next hit on its back
+    fn spans(&self, ray: &Ray) -> Vec<Span> {
+        let mut spans = Vec::new();
+        let mut enter: Option<Hit> = None;
+        let mut near = f32::NEG_INFINITY;
+        while let Some(hit) = self.hit(ray, near, f32::INFINITY) {
+            let next = hit.length + hit.length.abs().max(1.0) * 1e-5;
+            // a NaN length would otherwise never move forward
+            if next.is_nan() || next <= near {
+                break;
+            }
+            near = next;
+            if hit.front {
+                enter = Some(hit);
+            } else if let Some(enter) = enter.take() {
+                spans.push(Span { enter, exit: hit });
+            }
+        }
+        spans
+    }
+}
+
+/// everything that can be seen
 pub struct World {
-    spheres: Vec<Sphere>,
+    objects: Vec<Arc<dyn Hittable>>,
+    /// built the first time the world is hit, and thrown away when objects are added
+    accel: OnceLock<Accel>,
+    volumes: Vec<Volume>,
+    /// a medium filling all of space, like haze
+    atmosphere: Option<Medium>,
+    /// one color seen in every direction instead of the sky
+    background: Option<Spectrum>,
+    lights: Vec<Light>,
+    light_sampling: LightSampling,
+    /// built the first time a light is sampled, and thrown away when lights are added
+    light_picker: OnceLock<LightPicker>,
+}
+
+/// the acceleration structure over a world's objects
+struct Accel {
+    bvh: Bvh,
+    /// indices of the objects the tree holds
+    bounded: Vec<usize>,
+    /// indices of objects that go on forever, which are always tested
+    unbounded: Vec<usize>,
+}
+
+impl Accel {
+    fn new(objects: &[Arc<dyn Hittable>]) -> Accel {
+        let mut bounded = Vec::new();
+        let mut bounds = Vec::new();
+        let mut unbounded = Vec::new();
+        for (index, object) in objects.iter().enumerate() {
+            match object.bounds() {
+                Some(b) => {
+                    bounded.push(index);
+                    bounds.push(b);
+                }
+                None => unbounded.push(index),
+            }
+        }
+        Accel { bvh: Bvh::new(&bounds), bounded, unbounded }
+    }
+}
+
+impl Default for World {
+    fn default() -> World {
+        World::new()
+    }
 }
 
 impl World {
+    /// a world with nothing in it but the sky
+    pub fn empty() -> World {
+        World { objects: Vec::new(), accel: OnceLock::new(), volumes: Vec::new(), atmosphere: None, background: None, lights: Vec::new(), light_sampling: LightSampling::default(), light_picker: OnceLock::new() }
+    }
+    /// adds an object to the world
+    pub fn with(self, object: impl Hittable + 'static) -> Self {
+        self.with_shared(Arc::new(object))
+    }
+    /// adds an object that may also be used elsewhere, such as by an instance
+    pub fn with_shared(mut self, object: Arc<dyn Hittable>) -> Self {
+        self.objects.push(object);
+        self.accel = OnceLock::new();
+        // the picker is sized to the objects
+        self.light_picker = OnceLock::new();
+        self
+    }
+    /// adds every visible object under a scene graph node, placed by its
+    /// transforms and with inherited materials applied
+    pub fn with_scene(self, root: &Node) -> Self {
+        root.flatten()
+            .into_iter()
+            .fold(self, |world, instance| world.with(instance))
+    }
+    /// replaces the sky with one color in every direction, given as rgb or a spectrum
+    pub fn with_background(mut self, color: impl Into<Spectrum>) -> Self {
+        self.background = Some(color.into());
+        self
+    }
+    /// adds a medium filling the inside of a closed shape
+    pub fn with_volume(mut self, volume: Volume) -> Self {
+        self.volumes.push(volume);
+        self
+    }
+    /// adds a light, which is only seen by what it lights
+    pub fn with_light(mut self, light: Light) -> Self {
+        self.lights.push(light);
+        self.light_picker = OnceLock::new();
+        self
+    }
+    /// adds a mesh glowing with `radiance` from the front of every triangle. each
+    /// triangle is a light too, so surfaces find the glow by sampling the lights
+    pub fn with_emitter(self, mesh: Mesh, radiance: impl Into<Spectrum>) -> Self {
+        let radiance = radiance.into();
+        let lights: Vec<Light> = (0..mesh.triangle_count()).map(|i| Light::triangle(mesh.triangle(i), radiance.clone())).collect();
+        lights
+            .into_iter()
+            .fold(self, World::with_light)
+            .with(mesh.with_material(Material::Emissive(radiance)))
+    }
+    /// changes how lights are chosen to be sampled
+    pub fn with_light_sampling(mut self, sampling: LightSampling) -> Self {
+        self.light_sampling = sampling;
+        self.light_picker = OnceLock::new();
+        self
+    }
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+    /// light reaching `pos` straight from one of the lights, picked by how much
+    /// it could light the point and weighted for the chance of picking it. `normal`
+    /// is the surface's, if there is one. blocking isn't checked
+    pub fn sample_light(&self, pos: &Vector, normal: Option<&Vector>, wavelengths: &Wavelengths) -> Option<Incoming> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let picker = self.light_picker.get_or_init(|| LightPicker::new(&self.lights, self.light_sampling, self.radius()));
+        let (index, chance) = picker.pick(pos, normal)?;
+        let incoming = self.lights[index].sample(pos, wavelengths)?;
+        Some(Incoming { light: incoming.light * chance.recip(), ..incoming })
+    }
+    /// half the diagonal of a box around every bounded object
+    fn radius(&self) -> f32 {
+        let bounds = self.objects.iter().filter_map(|object| object.bounds()).fold(Aabb::EMPTY, |b, o| b.union(&o));
+        let radius = (bounds.max - bounds.min).length() / 2.0;
+        if radius.is_finite() { radius } else { 1.0 }
+    }
+    /// the fraction of light from `incoming` that makes it to `pos`: none if a
+    /// surface is in the way, and what the media let through otherwise. from
+    /// inside a translucent object, its own surface lets some of the light in
+    pub fn unblocked(&self, pos: &Vector, incoming: &Incoming, wavelengths: &Wavelengths) -> Color {
+        let shadow = Ray::new(*pos, incoming.direction).with_wavelengths(*wavelengths);
+        let mut surfaces = Color::WHITE;
+        let mut from = *pos;
+        loop {
+            let ray = Ray::new(from, incoming.direction).with_wavelengths(*wavelengths);
+            let left = incoming.distance - (from - *pos).length();
+            // short of the light, which may be a glowing surface itself
+            let Some(hit) = self.hit(&ray).filter(|hit| hit.length < left - SURFACE_GAP) else { break };
+            match hit.material.shadow_through(&hit) {
+                Some(through) => surfaces = surfaces * through,
+                None => return Color::BLACK,
+            }
+            from = hit.pos;
+        }
+        let near = SURFACE_GAP;
+        let far = incoming.distance;
+        self
+            .atmosphere
+            .iter()
+            .map(|medium| medium.transmittance_between(&shadow, near, far))
+            .chain(self.volumes.iter().flat_map(|volume| {
+                volume
+                    .segments(&shadow, near, far)
+                    .into_iter()
+                    .map(|(start, end)| volume.medium().transmittance_between(&shadow, start, end))
+            }))
+            .fold(surfaces, |t, segment| t * segment)
+    }
+    /// fills all of space with a medium
+    pub fn with_atmosphere(mut self, medium: Medium) -> Self {
+        self.atmosphere = Some(medium);
+        self
+    }
+    /// samples where the ray first scatters in the world's media before reaching `far`,
+    /// along with `interior`, the medium inside the object the ray is walking through
+    pub fn sample_media(&self, ray: &Ray, far: f32, interior: Option<&dyn Participating>) -> Event {
+        if self.volumes.is_empty() && self.atmosphere.is_none() && interior.is_none() {
+            return Event::Pass { weight: Color::WHITE };
+        }
+        let near = SURFACE_GAP;
+        let media: Vec<_> = self
+            .atmosphere
+            .iter()
+            .map(|medium| medium as &dyn Participating)
+            .chain(interior)
+            .map(|medium| (medium, vec![(near, far)]))
+            .chain(self.volumes.iter().map(|volume| (volume.medium(), volume.segments(ray, near, far))))
+            .collect();
+        sample_media(ray, far, &media)
+    }
+    /// the nearest surface along the ray, if any
     pub fn hit(&self, ray: &Ray) -> Option<Hit> {
-        self.spheres
+        let accel = self.accel.get_or_init(|| Accel::new(&self.objects));
+        let near = SURFACE_GAP;
+        // holes cut in masked materials don't count as hits, so look past them
+        let hit_object = |index: usize, far| {
+            let mut near = near;
+            loop {
+                let hit = self.objects[index].hit(ray, near, far)?;
+                let hit = Hit { pick: hit.material.mixes().then(random), ..hit };
+                if hit.material.is_solid(&hit) {
+                    return Some(Hit { object: index, ..hit });
+                }
+                near = hit.length + hit.length.abs().max(1.0) * 1e-5;
+            }
+        };
+        let bounded = accel.bvh.hit(ray, near, f32::INFINITY, |i, far| hit_object(accel.bounded[i], far));
+        accel
+            .unbounded
             .iter()
-            .filter_map(|Sphere { pos, radius, material  }| {
-                ray.hit_sphere(*pos, *radius, f32::EPSILON, f32::INFINITY, material)
-            })
-            .filter(|h| h.front)
+            .filter_map(|&index| hit_object(index, f32::INFINITY))
+            .chain(bounded)
             .min_by(|a, b| {
-                (&a.length)
+                a.length
                     .partial_cmp(&b.length)
                     .unwrap_or(Ordering::Equal)
             })
     }
+    /// the demo scene: a hollow glass sphere between red and blue metal spheres on a gray floor
     pub fn new() -> World {
-        let spheres = vec![
+        let shell = Csg::difference(
             Sphere::new(0.0, 0.0, -1.0, 0.5).with_material(Material::TEST_DIE),
-            Sphere::new(0.0, 0.0, -1.0, -0.45).with_material(Material::TEST_DIE),
-            Sphere::new(1.0, 0.0, -1.0, 0.4).with_material(Material::TEST_METAL_RED),
-            Sphere::new(-1.0, 0.0, -1.0, 0.4).with_material(Material::TEST_METAL_BLUE),
-            Sphere::new(2.0, -1000.5, -1.0, 1000.0),
-        ];
-
-        World { spheres }
+            Sphere::new(0.0, 0.0, -1.0, 0.45).with_material(Material::TEST_DIE),
+        );
+        World::empty()
+            .with(shell)
+            .with(Sphere::new(1.0, 0.0, -1.0, 0.4).with_material(Material::TEST_METAL_RED))
+            .with(Sphere::new(-1.0, 0.0, -1.0, 0.4).with_material(Material::TEST_METAL_BLUE))
+            .with(Plane::new(Vector::new(0.0, -0.5, 0.0), Vector::new(0.0, 1.0, 0.0)))
     }
     pub fn background_color(&self, ray: &Ray) -> Color {
+        if let Some(spectrum) = &self.background {
+            return spectrum.sample(&ray.wavelengths);
+        }
         let unit_direction = ray.direction.unit();
         let horizon = 0.5 * (unit_direction.y + 1.0);
-        Color::blend(Color::WHITE, Color::GRADE, horizon)
+        ray.wavelengths.illuminant(&Color::blend(Color::WHITE, Color::GRADE, horizon))
     }
 }
 
@@ -49,6 +301,7 @@ pub struct Sphere {
 }
 
 impl Sphere {
+    /// a gray diffuse sphere at the given position
     pub fn new(x: f32, y: f32, z: f32, radius: f32,) -> Sphere {
         let pos = Vector::new(x,y,z);
         let material = Arc::new(Material::new());
@@ -59,3 +312,22 @@ impl Sphere {
         self
     }
 }
+
+impl Hittable for Sphere {
+    fn hit(&self, ray: &Ray, near: f32, far: f32) -> Option<Hit> {
+        let hit = ray.hit_sphere(self.pos, self.radius, near, far, &self.material)?;
+        // longitude and latitude, with the poles on the y axis
+        let out = (hit.pos - self.pos) / self.radius.abs();
+        let u = ((-out.z).atan2(out.x) + PI) / (2.0 * PI);
+        let v = (-out.y).clamp(-1.0, 1.0).acos() / PI;
+        // u goes around the y axis
+        Some(hit.with_uv(u, v).with_tangent(Vector::new(out.z, 0.0, -out.x)))
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        let r = self.radius.abs();
+        let extent = Vector::new(r, r, r);
+        Some(Aabb::new(self.pos - extent, self.pos + extent))
+    }
+}
+
+impl Solid for Sphere {}
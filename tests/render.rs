@@ -0,0 +1,434 @@
+use std::sync::Arc;
+
+use weekend_raytrace::{
+    aov::Aov,
+    camera::Camera,
+    checkpoint::Checkpoint,
+    instance::Instance,
+    integrator::Integrator,
+    light::Light,
+    light_tree::LightSampling,
+    material::{Mask, Material},
+    microfacet::Film,
+    medium::{Medium, Volume},
+    mesh::Mesh,
+    numbers::{Aabb, Color, Transform, Vector},
+    texture::Bump,
+    render::{RenderSettings, Renderer},
+    shapes::{Cuboid, Plane},
+    spectrum::Ior,
+    voxel::{Grid, VoxelMedium},
+    world::{Sphere, World},
+};
+
+fn small(samples: usize, samples_per_pass: usize, seed: u64) -> RenderSettings {
+    RenderSettings {
+        width: 12,
+        height: 8,
+        samples,
+        samples_per_pass,
+        seed,
+        ..Default::default()
+    }
+}
+
+fn camera() -> Camera {
+    Camera::new(3.0, 2.0)
+}
+
+/// a wall facing the camera from 2 away, filling the view
+fn wall(material: Material) -> Plane {
+    Plane::new(Vector::new(0.0, 0.0, -2.0), Vector::Z_POS).with_material(material)
+}
+
+/// a red wall with a metal ball of this roughness in front of it around pixel
+/// (6, 5), lit by a bulb up and to the left
+fn ball_by_a_wall(roughness: f32) -> World {
+    World::empty()
+        .with(wall(Material::Diffuse(0.0, Color::REDDISH)))
+        .with(Sphere::new(0.5, 0.0, -1.5, 0.5).with_material(Material::Metal(roughness, Color::GRAY)))
+        .with_light(Light::point(Vector::new(-1.0, 1.0, -1.0), Color::gray(2.0)))
+}
+
+#[test]
+fn empty_world_shows_the_sky() {
+    let image = Renderer::new(small(2, 1, 0)).render(&World::empty(), &camera());
+    assert_eq!(image.width(), 12);
+    assert_eq!(image.height(), 8);
+    assert_eq!(image.pixels().len(), 12 * 8);
+    // the sky fades from white at the horizon to blue overhead
+    let top = image.pixel(6, 0);
+    let bottom = image.pixel(6, 7);
+    assert!(top.r() < bottom.r());
+    assert!((top.b() - 1.0).abs() < 1e-4);
+}
+
+#[test]
+fn equal_seeds_give_equal_images() {
+    let world = World::new();
+    let a = Renderer::new(small(4, 2, 3)).render(&world, &camera());
+    let b = Renderer::new(small(4, 2, 3)).render(&world, &camera());
+    let c = Renderer::new(small(4, 2, 4)).render(&world, &camera());
+    assert!(a.pixels() == b.pixels());
+    assert!(a.pixels() != c.pixels());
+}
+
+#[test]
+fn spheres_block_the_sky() {
+    let world = World::empty().with(Sphere::new(0.0, 0.0, -1.0, 0.9).with_material(Material::TEST_METAL_RED));
+    let image = Renderer::new(small(4, 4, 0)).render(&world, &camera());
+    // red metal only reflects red
+    let center = image.pixel(6, 4);
+    assert!(center.g() < 0.05);
+    assert!(center.r() > center.g());
+}
+
+#[test]
+fn resume_matches_uninterrupted() {
+    let world = World::new();
+    let renderer = Renderer::new(small(6, 2, 5));
+
+    let mut straight = renderer.start();
+    while straight.passes < renderer.total_passes(&straight) {
+        renderer.render_pass(&world, &camera(), &mut straight, || ());
+    }
+
+    let mut first = renderer.start();
+    renderer.render_pass(&world, &camera(), &mut first, || ());
+    let path = std::env::temp_dir().join(format!("resume-test-{}.bin", std::process::id()));
+    first.save(&path).unwrap();
+    let mut resumed = Checkpoint::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    while resumed.passes < renderer.total_passes(&resumed) {
+        renderer.render_pass(&world, &camera(), &mut resumed, || ());
+    }
+    assert!(resumed == straight);
+    assert!(renderer.render(&world, &camera()).pixels() == straight.film.to_image().pixels());
+
+    // a spectral render carries on tracing wavelengths from its checkpoint
+    let spectral = Renderer::new(RenderSettings { spectral: true, ..small(6, 2, 5) });
+    let mut first = spectral.start();
+    spectral.render_pass(&world, &camera(), &mut first, || ());
+    first.save(&path).unwrap();
+    let mut resumed = Checkpoint::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    while resumed.passes < renderer.total_passes(&resumed) {
+        renderer.render_pass(&world, &camera(), &mut resumed, || ());
+    }
+    assert!(resumed.spectral && resumed.film.to_image().pixels() == spectral.render(&world, &camera()).pixels());
+}
+
+#[test]
+fn instances_share_one_mesh() {
+    let triangle = Arc::new(Mesh::new(
+        vec![Vector::new(-0.5, -0.5, 0.0), Vector::new(0.5, -0.5, 0.0), Vector::new(0.0, 0.5, 0.0)],
+        vec![[0, 1, 2]],
+    ));
+    let mut world = World::empty();
+    for n in 0..100 {
+        let x = (n % 10) as f32 * 0.3 - 1.35;
+        let y = (n / 10) as f32 * 0.2 - 0.9;
+        let place = Transform::scale(0.4, 0.4, 0.4)
+            .then(&Transform::rotate_z(n as f32))
+            .then(&Transform::translate(x, y, -1.0));
+        world = world.with(Instance::new(triangle.clone(), place).with_material(Material::TEST_METAL_RED));
+    }
+    assert_eq!(Arc::strong_count(&triangle), 101);
+    let image = Renderer::new(small(2, 2, 0)).render(&world, &camera());
+    assert!(image.pixels().iter().any(|c| c.g() < 0.05));
+}
+
+#[test]
+fn smoke_dims_what_is_behind_it() {
+    let clear = Renderer::new(small(8, 8, 0)).render(&World::empty(), &camera());
+    let smoke = Volume::new(Sphere::new(0.0, 0.0, -2.0, 1.0), Medium::fog(3.0, Color::gray(0.5), 0.3));
+    let world = World::empty().with_volume(smoke);
+    let image = Renderer::new(small(8, 8, 0)).render(&world, &camera());
+    // through the middle of the ball, but clear sky around its edge
+    assert!(image.pixel(6, 4).b() < 0.5 * clear.pixel(6, 4).b());
+    assert!(image.pixel(0, 0) == clear.pixel(0, 0));
+}
+
+#[test]
+fn fire_glows() {
+    let size = 4;
+    let grid = Grid::new([size; 3], vec![4.0; size * size * size])
+        .unwrap()
+        .with_emission(vec![Color::new(8.0, 2.0, 0.5); size * size * size])
+        .unwrap();
+    let (min, max) = (Vector::new(-0.5, -0.5, -2.5), Vector::new(0.5, 0.5, -1.5));
+    let fire = VoxelMedium::new(grid, Aabb::new(min, max)).with_albedo(Color::gray(0.2));
+    let world = World::empty().with_volume(Volume::new(Cuboid::new(min, max), fire));
+    let image = Renderer::new(small(8, 8, 0)).render(&world, &camera());
+    let center = image.pixel(6, 4);
+    assert!(center.r() > 1.0 && center.r() > center.b());
+}
+
+#[test]
+fn white_furnace() {
+    // a white diffuse sphere under an evenly white sky sends back all the light
+    // that falls on it, so it disappears. rough diffuse loses a little to the
+    // light its facets trade with each other, but never gains any
+    for (roughness, lowest) in [(0.0, 1.0), (0.5, 0.75)] {
+        let world = World::empty()
+            .with_background(Color::WHITE)
+            .with(Sphere::new(0.0, 0.0, -1.0, 0.9).with_material(Material::Diffuse(roughness, Color::WHITE)));
+        let image = Renderer::new(small(64, 64, 0)).render(&world, &camera());
+        let center = image.pixel(6, 4);
+        assert!(center.r() >= lowest - 1e-5 && center.r() <= 1.0 + 1e-5);
+    }
+}
+
+#[test]
+fn layered_materials_keep_their_light() {
+    let furnace = |material: Material| {
+        let world = World::empty()
+            .with_background(Color::WHITE)
+            .with(Sphere::new(0.0, 0.0, -1.0, 0.9).with_material(material));
+        let image = Renderer::new(small(256, 64, 0)).render(&world, &camera());
+        image.pixel(6, 4).r()
+    };
+    let white = || Material::Diffuse(0.0, Color::WHITE);
+    let black = || Material::Diffuse(0.0, Color::BLACK);
+    // a quarter of the time the surface is black
+    assert!((furnace(Material::mix(white(), black(), 0.25)) - 0.75).abs() < 0.08);
+    // a clear coat only moves light around between the layers, losing a little
+    // to the rare paths that bounce around inside for too long
+    let varnished = furnace(Material::coated(white(), 1.5, 0.2));
+    assert!(varnished > 0.9 && varnished <= 1.0 + 1e-5);
+}
+
+#[test]
+fn cutouts_let_the_sky_through() {
+    let sky = Renderer::new(small(64, 64, 0)).render(&World::empty(), &camera());
+    let masked = |opacity: f32, mask: Mask| {
+        let material = Material::masked(Material::TEST_METAL_RED, opacity, mask);
+        let world = World::empty().with(Sphere::new(0.0, 0.0, -1.0, 0.9).with_material(material));
+        Renderer::new(small(64, 64, 0)).render(&world, &camera())
+    };
+    assert!(masked(0.3, Mask::Threshold(0.5)).pixels() == sky.pixels());
+    assert!(masked(0.7, Mask::Threshold(0.5)).pixel(6, 4).g() < 0.05);
+    // holes cut in a material mixed with another still show, where it's the one used
+    let mixed = |weight: f32| {
+        let hole = Material::masked(Material::TEST_METAL_RED, 0.0, Mask::Threshold(0.5));
+        let material = Material::mix(Material::TEST_METAL_RED, hole, weight);
+        let world = World::empty().with(Sphere::new(0.0, 0.0, -1.0, 0.9).with_material(material));
+        Renderer::new(small(64, 64, 0)).render(&world, &camera())
+    };
+    // picking a side takes a random number, so the sky is only about the same
+    let through = mixed(1.0).pixels().iter().zip(sky.pixels()).all(|(a, b)| (a.g() - b.g()).abs() < 0.02);
+    assert!(through && mixed(0.0).pixel(6, 4).g() < 0.05);
+    // a wall half black and half holes in white: the white side is never seen,
+    // since where it's picked the ray goes through to the sky
+    let holey = Material::masked(Material::Diffuse(0.0, Color::WHITE), 0.0, Mask::Threshold(0.5));
+    let world = World::empty().with_background(Color::WHITE).with(wall(Material::mix(Material::Diffuse(0.0, Color::BLACK), holey, 0.5)));
+    let half = Renderer::new(small(256, 64, 0)).render(&world, &camera()).pixel(6, 4).g();
+    assert!((half - 0.5).abs() < 0.08, "{half}");
+    // half the rays get through each side of the sphere, and the red metal
+    // takes the green out of the rest
+    let soft = masked(0.5, Mask::Stochastic).pixel(6, 4).g() / sky.pixel(6, 4).g();
+    assert!((soft - 0.25).abs() < 0.1);
+}
+
+#[test]
+fn bumps_turn_surfaces_toward_the_sky() {
+    // the sky is bluer overhead than at the horizon, so a wall takes on less
+    // red wherever its normals lean up and more where they lean down
+    let look = |bump: Option<Color>| {
+        let diffuse = Material::Diffuse(0.0, Color::WHITE);
+        let material = match bump {
+            Some(normal) => Material::bumped(diffuse, Bump::Normal(normal.into())),
+            None => diffuse,
+        };
+        Renderer::new(small(64, 64, 0)).render(&World::empty().with(wall(material)), &camera()).pixel(6, 4).r()
+    };
+    let plain = look(None);
+    assert!((look(Some(Color::new(0.5, 0.5, 1.0))) - plain).abs() < 1e-4);
+    let (up, down) = (look(Some(Color::new(0.5, 0.8, 0.9))), look(Some(Color::new(0.5, 0.2, 0.9))));
+    assert!(up < plain - 0.05 && down > plain + 0.05, "{up} {plain} {down}");
+}
+
+#[test]
+fn dispersion_and_films_split_colors() {
+    // a glass ball over the line between a black floor and a white sky: glass
+    // bending every color alike only ever shows gray, while glass that bends
+    // blue more than red fringes the line with color, and so does a thin film
+    // reflecting some colors more than others
+    let look = |glass: Material| {
+        let floor = Plane::new(Vector::new(0.0, -0.05, 0.0), Vector::new(0.0, 1.0, 0.0)).with_material(Material::Diffuse(0.0, Color::BLACK));
+        let ball = Sphere::new(0.0, 0.0, -1.5, 0.6).with_material(glass);
+        let world = World::empty().with_background(Color::WHITE).with(floor).with(ball);
+        let image = Renderer::new(small(256, 64, 1)).render(&world, &camera());
+        image.pixels().iter().map(|c| (c.r() - c.b()).abs()).fold(0.0, f32::max)
+    };
+    assert!(look(Material::dispersive(Ior::Constant(1.78))) < 1e-4);
+    assert!(look(Material::dispersive(Ior::SF11)) > 0.08);
+    assert!(look(Material::dispersive(Ior::Constant(1.78)).with_film(Film::new(400.0, 1.33)).unwrap()) > 0.02);
+    // films only go over metal and glass bending every color alike
+    assert!(Material::Diffuse(0.0, Color::WHITE).with_film(Film::new(400.0, 1.33)).is_none());
+    assert!(Material::dispersive(Ior::SF11).with_film(Film::new(400.0, 1.33)).is_none());
+}
+
+#[test]
+fn spectral_mode_matches_rgb() {
+    // a diffuse sphere under an even sky looks the same either way, give or
+    // take the noise of drawing wavelengths
+    let world = World::empty()
+        .with_background(Color::new(0.9, 0.8, 0.6))
+        .with(Sphere::new(0.0, 0.0, -1.0, 0.9).with_material(Material::Diffuse(0.0, Color::new(0.2, 0.5, 0.8))));
+    let rgb = Renderer::new(small(256, 64, 0)).render(&world, &camera());
+    let spectral = Renderer::new(RenderSettings { spectral: true, ..small(256, 64, 0) }).render(&world, &camera());
+    for (x, y) in [(6, 4), (0, 0)] {
+        for c in 0..3 {
+            let (a, b) = (rgb.pixel(x, y).channel(c), spectral.pixel(x, y).channel(c));
+            assert!((a - b).abs() < 0.08, "{} {} channel {}: {} vs {}", x, y, c, a, b);
+        }
+    }
+}
+
+#[test]
+fn light_wanders_through_wax() {
+    let furnace = |material: Material| {
+        let world = World::empty()
+            .with_background(Color::WHITE)
+            .with(Sphere::new(0.0, 0.0, -1.0, 0.9).with_material(material));
+        Renderer::new(small(64, 64, 0)).render(&world, &camera()).pixel(6, 4).clone()
+    };
+    // nothing inside absorbs, so every path comes back out to the sky
+    let clear = furnace(Material::subsurface(Color::WHITE, Color::gray(0.2), 1.4, 0.0));
+    assert!((clear.g() - 1.0).abs() < 1e-3);
+    let frosted = furnace(Material::subsurface(Color::WHITE, Color::gray(0.2), 1.4, 0.3));
+    assert!(frosted.g() > 0.9 && frosted.g() <= 1.0 + 1e-5, "{:?}", frosted);
+    // walks too long to follow in wax this dense end dark, whatever the color
+    let dense = furnace(Material::subsurface(Color::WHITE, Color::gray(0.001), 1.4, 0.0));
+    assert!(dense.r() == dense.g() && dense.g() == dense.b() && dense.g() < 1.0, "{:?}", dense);
+    // red goes deeper and comes back more often than blue
+    let skin = furnace(Material::subsurface(Color::new(0.95, 0.6, 0.4), Color::new(0.3, 0.1, 0.05), 1.4, 0.0));
+    assert!(skin.r() > skin.g() && skin.g() > skin.b() && skin.r() < 1.0);
+    // lit only by a point light, which light scattering inside finds through the surface
+    let wax = Sphere::new(0.0, 0.0, -1.0, 0.9).with_material(Material::subsurface(Color::gray(0.9), Color::gray(0.2), 1.4, 0.0));
+    let world = World::empty()
+        .with_background(Color::BLACK)
+        .with(wax)
+        .with_light(Light::point(Vector::new(0.0, 2.0, 0.0), Color::gray(4.0)));
+    let lit = Renderer::new(small(64, 64, 0)).render(&world, &camera()).pixel(6, 4).g();
+    assert!(lit > 0.01 && lit < 1.0, "{}", lit);
+}
+
+#[test]
+fn lights_reach_what_they_shine_on() {
+    let dark = || World::empty().with_background(Color::BLACK).with(wall(Material::Diffuse(0.0, Color::WHITE)));
+    // a white wall facing light that gives pi per unit of area sends back exactly 1
+    let sun = Light::directional(Vector::Z_NEG, Color::gray(std::f32::consts::PI), 0.0);
+    let image = Renderer::new(small(4, 4, 0)).render(&dark().with_light(sun), &camera());
+    assert!(image.pixels().iter().all(|c| (c.g() - 1.0).abs() < 1e-4));
+    // where the middle pixel sees the wall
+    let target = Vector::new(0.54, 0.57, -2.0);
+    // a narrow spot lights it and leaves the corners dark
+    let spot = Light::spot(target + Vector::new(0.0, 0.0, 1.0), Vector::Z_NEG, Color::gray(4.0), 0.3, 0.4);
+    let image = Renderer::new(small(4, 4, 0)).render(&dark().with_light(spot), &camera());
+    assert!(image.pixel(6, 4).g() > 0.5 && image.pixel(0, 0).g() == 0.0);
+    // and a ball in the way of a bulb off to the side leaves a shadow
+    let bulb = Light::point(target + Vector::new(1.0, 0.0, 1.0), Color::gray(4.0));
+    let ball = Sphere::new(1.04, 0.57, -1.5, 0.15).with_material(Material::Diffuse(0.0, Color::WHITE));
+    let shadowed = Renderer::new(small(4, 4, 0)).render(&dark().with_light(bulb.clone()).with(ball), &camera());
+    let lit = Renderer::new(small(4, 4, 0)).render(&dark().with_light(bulb), &camera());
+    assert!(lit.pixel(6, 4).g() > 0.2 && shadowed.pixel(6, 4).g() < lit.pixel(6, 4).g() * 0.5);
+}
+
+#[test]
+fn many_lights_add_up_however_they_are_picked() {
+    // a row of dim bulbs in front of the wall, and one bright one far off to the side
+    let world = |sampling| {
+        let bulbs = (0..30).map(|i| Light::point(Vector::new(i as f32 * 0.1 - 1.5, 0.5, -1.0), Color::gray(0.3)));
+        bulbs
+            .fold(World::empty().with_background(Color::BLACK).with(wall(Material::Diffuse(0.0, Color::WHITE))), World::with_light)
+            .with_light(Light::point(Vector::new(20.0, 0.0, 5.0), Color::gray(200.0)))
+            .with_light_sampling(sampling)
+    };
+    let average = |sampling| {
+        let world = world(sampling);
+        let image = Renderer::new(small(512, 256, 5)).render(&world, &camera());
+        image.pixels().iter().map(|c| c.g()).sum::<f32>() / image.pixels().len() as f32
+    };
+    let uniform = average(LightSampling::Uniform);
+    for sampling in [LightSampling::Power, LightSampling::Tree] {
+        let picked = average(sampling);
+        assert!((picked - uniform).abs() < 0.05 * uniform, "{sampling:?}: {picked} against {uniform}");
+    }
+}
+
+#[test]
+fn glowing_meshes_light_what_they_face() {
+    let dark = || World::empty().with_background(Color::BLACK).with(wall(Material::Diffuse(0.0, Color::WHITE)));
+    let look = |world: World| Renderer::new(small(64, 64, 0)).render(&world, &camera()).pixel(6, 4).g();
+    // where the middle pixel sees the wall
+    let target = Vector::new(0.54, 0.57, -2.0);
+    let square = |size: f32, z: f32, facing_wall: bool| {
+        let h = size / 2.0;
+        let corners = [(-h, -h), (h, -h), (h, h), (-h, h)].map(|(x, y)| Vector::new(target.x + x, target.y + y, z));
+        let triangles = if facing_wall { vec![[0, 2, 1], [0, 3, 2]] } else { vec![[0, 1, 2], [0, 2, 3]] };
+        Mesh::new(corners.to_vec(), triangles)
+    };
+    // a glowing square behind the camera, as wide as it is far from the wall, is
+    // seen from there with a view factor of 0.2394, counted once whether sampled
+    // or run into
+    let glowing = look(dark().with_emitter(square(3.0, 1.0, true), Color::gray(4.0)));
+    assert!((glowing - 4.0 * 0.2394).abs() < 0.03, "{glowing}");
+    // seen from the front it shows its radiance, and from behind it's dark
+    let seen = |facing_wall| look(World::empty().with_background(Color::BLACK).with_emitter(square(2.0, -1.0, facing_wall), Color::gray(3.0)));
+    assert!((seen(false) - 3.0).abs() < 1e-4 && seen(true) == 0.0);
+}
+
+#[test]
+fn debug_integrators_show_the_surface() {
+    let world = World::empty().with(wall(Material::Diffuse(0.0, Color::WHITE)));
+    let view = |integrator| Renderer::new(RenderSettings { integrator, ..small(4, 4, 0) }).render(&world, &camera());
+    // the wall faces the camera, which looks at it from 2 away at the closest
+    let normals = view(Integrator::Normals);
+    assert!(normals.pixels().iter().all(|c| (c.b() - 1.0).abs() < 1e-5 && (c.r() - 0.5).abs() < 0.01));
+    let depth = view(Integrator::Depth { far: 8.0 });
+    assert!(depth.pixels().iter().all(|c| c.g() > 0.4 && c.g() <= 0.75 + 1e-5));
+    // nothing stands in front of a lone wall, and nothing there bounces twice
+    assert!(view(Integrator::AmbientOcclusion { radius: 1.0 }).pixels().iter().all(|c| c.g() == 1.0));
+    assert!(view(Integrator::Bounces { most: 2 }).pixels().iter().all(|c| c.g() == 1.0));
+    // only triangles have barycentric coordinates
+    assert!(view(Integrator::Barycentrics).pixels().iter().all(|c| *c == Color::BLACK));
+}
+
+#[test]
+fn passes_add_up_to_the_picture() {
+    let world = ball_by_a_wall(0.2);
+    let (image, passes) = Renderer::new(small(8, 4, 0)).render_with_aovs(&world, &camera(), &Aov::ALL);
+    let pass = |aov: Aov| &passes.iter().find(|(a, _)| *a == aov).unwrap().1;
+    for (x, y) in [(0, 0), (6, 4), (6, 5), (11, 7)] {
+        let parts = [Aov::DirectDiffuse, Aov::IndirectDiffuse, Aov::Specular, Aov::Emission];
+        let sum = parts.iter().fold(Color::BLACK, |sum, aov| sum + pass(aov.clone()).pixel(x, y).clone());
+        assert!((sum.g() - image.pixel(x, y).g()).abs() < 1e-4);
+    }
+    // the ball is in front of the wall, and nothing is shiny but the ball
+    let ids = pass(Aov::ObjectId);
+    assert!(ids.channels() == 1 && ids.value(0, 0, 0) == 1.0 && ids.value(6, 5, 0) == 2.0);
+    assert!(*pass(Aov::Specular).pixel(0, 0) == Color::BLACK && pass(Aov::Specular).pixel(6, 5).g() > 0.0);
+    assert!(pass(Aov::Depth).value(6, 5, 0) < pass(Aov::Depth).value(0, 0, 0));
+    assert!((pass(Aov::Albedo).pixel(0, 0).r() - Color::REDDISH.r()).abs() < 1e-4);
+}
+
+#[test]
+fn light_path_expressions_pick_out_paths() {
+    let world = ball_by_a_wall(0.0);
+    let aovs = [
+        Aov::DirectDiffuse,
+        Aov::custom("direct", "C<RD>[LB]").unwrap(),
+        Aov::custom("everything", "C.*").unwrap(),
+        Aov::custom("mirrored", "CS+.*").unwrap(),
+    ];
+    let (image, passes) = Renderer::new(small(8, 4, 0)).render_with_aovs(&world, &camera(), &aovs);
+    let pass = |name: &str| &passes.iter().find(|(a, _)| a.name() == name).unwrap().1;
+    for (x, y) in [(0, 0), (6, 4), (6, 5), (11, 7)] {
+        assert!((pass("direct").pixel(x, y).g() - pass("direct-diffuse").pixel(x, y).g()).abs() < 1e-5);
+        assert!((pass("everything").pixel(x, y).g() - image.pixel(x, y).g()).abs() < 1e-4);
+    }
+    // only the polished ball is seen in a mirror
+    assert!(*pass("mirrored").pixel(0, 0) == Color::BLACK && pass("mirrored").pixel(6, 5).g() > 0.0);
+}